@@ -0,0 +1,64 @@
+//! Compares the full collision pipeline with the early-exit check, on random
+//! configurations of the panda.
+//!
+//! Run with `cargo bench --bench collision`.
+
+use std::f32::consts::PI;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use robotsim::robot::Robot;
+
+const PANDA_URDF: &str = "assets/panda/urdf/panda_relative.urdf";
+const NUM_CONFIGS: usize = 1_000;
+
+fn random_configs(robot: &Robot) -> Vec<Vec<f32>> {
+    let limits: Vec<(f32, f32)> = robot
+        .robot_chain
+        .iter_joints()
+        .map(|joint| joint.limits.map_or((-PI, PI), |l| (l.min, l.max)))
+        .collect();
+
+    let mut rng = SmallRng::seed_from_u64(42);
+    (0..NUM_CONFIGS)
+        .map(|_| {
+            limits
+                .iter()
+                .map(|(min, max)| {
+                    if min < max {
+                        rng.gen_range(*min..*max)
+                    } else {
+                        *min
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn collision_checking(c: &mut Criterion) {
+    let mut robot = Robot::from_file(PANDA_URDF, None).expect("Failed to load the panda");
+    let configs = random_configs(&robot);
+
+    let mut group = c.benchmark_group("panda");
+    group.bench_function("has_collision", |b| {
+        b.iter(|| {
+            for joints in &configs {
+                black_box(robot.collision_at(joints).unwrap());
+            }
+        })
+    });
+    group.bench_function("has_collision_fast", |b| {
+        b.iter(|| {
+            for joints in &configs {
+                black_box(robot.collision_at_fast(joints).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, collision_checking);
+criterion_main!(benches);
@@ -1,17 +1,33 @@
 use std::collections::HashMap;
 
-use numpy::{Ix1, Ix2, PyArrayLike1};
+use numpy::ndarray::{Array1, Array2, ArrayD};
+use numpy::{IntoPyArray, Ix1, Ix2, PyArray1, PyArray2, PyArrayDyn, PyArrayLike1};
 use numpy::{PyArrayLike, PyArrayLikeDyn};
 // use crfs_rs::{Attribute, Model};
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 
-use robotsim::robot::{ColliderHandle, CollisionResult, Robot, RobotError};
+use robotsim::robot::collision_matrix::CollisionMatrixGenerationOption;
+use robotsim::robot::srdf::disable_collisions_to_srdf;
+use robotsim::robot::{
+    pose_to_isometry, CollisionResult, ContactKind, IkOption, IkTarget, PointCloudOption, Robot,
+    RobotError, UrdfRobotOption,
+};
+use urdf_rs::{Geometry, Pose, Vec3};
 
-use eyre::Result;
+use eyre::{ensure, Result};
 
 #[feature(visualiser)]
 mod visualiser;
 
+mod scene;
+
+/// The 4x4 homogeneous matrix of a pose, row by row.
+fn homogeneous_row_major(pose: &robotsim::robot::Isometry<f32>) -> [f32; 16] {
+    let matrix = pose.to_homogeneous();
+    std::array::from_fn(|i| matrix[(i / 4, i % 4)])
+}
+
 #[pyclass(module = "robotsim", name = "Robot")]
 // #[self_referencing]
 struct PyRobot {
@@ -24,11 +40,36 @@ struct PyRobot {
 
 #[pymethods]
 impl PyRobot {
+    /// `mesh_collider_mode` is one of "trimesh", "convex_hull" or "convex_decomposition",
+    /// and can be overridden per link (by name) with `link_mesh_collider_modes`.
+    /// `base_joint` is one of "fixed", "planar" (x, y, theta) or "floating" (x, y, z, yaw,
+    /// pitch, roll), whose virtual joints then come first in the joint vector.
     #[new]
-    fn py_new(path: &str) -> PyResult<Self> {
+    #[pyo3(signature = (
+        path, srdf_path=None, mesh_collider_mode="trimesh", link_mesh_collider_modes=None,
+        base_joint="fixed"
+    ))]
+    fn py_new(
+        path: &str,
+        srdf_path: Option<&str>,
+        mesh_collider_mode: &str,
+        link_mesh_collider_modes: Option<HashMap<String, String>>,
+        base_joint: &str,
+    ) -> PyResult<Self> {
+        let option = UrdfRobotOption {
+            mesh_collider_mode: mesh_collider_mode.parse()?,
+            link_mesh_collider_modes: link_mesh_collider_modes
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(link, mode)| Ok((link, mode.parse()?)))
+                .collect::<Result<_>>()?,
+            base_joint: base_joint.parse()?,
+            ..Default::default()
+        };
+
         Ok(PyRobot {
             data: vec![5, 9],
-            robot: Robot::from_file(path)?,
+            robot: Robot::from_file_with_option(path, srdf_path, &option)?,
         })
     }
 
@@ -71,28 +112,235 @@ impl PyRobot {
         self.robot.joint_link_map.clone()
     }
 
+    /// Names of the active joints, i.e. the order of `set_joints` and `joints`.
     #[getter]
     fn joint_names(&self) -> Vec<String> {
-        // self.robot.name()
-        self.robot
-            .robot_chain
-            .iter_joints()
-            .map(|joint| joint.name.clone())
-            .collect()
+        self.robot.active_joint_names()
+    }
+
+    /// Same as `joint_names`.
+    #[getter]
+    fn active_joint_names(&self) -> Vec<String> {
+        self.robot.active_joint_names()
     }
 
+    /// Names of every movable joint, including the mimic joints.
+    #[getter]
+    fn movable_joint_names(&self) -> Vec<String> {
+        self.robot.movable_joint_names()
+    }
+
+    /// Position of every movable joint (including the mimic joints), by name.
+    #[getter]
+    fn joints_by_name(&self) -> HashMap<String, f32> {
+        self.robot.joint_positions_by_name()
+    }
+
+    /// Sets the joints of the dict (by name), keeping the others unchanged.
+    fn set_joints_by_name(&mut self, positions: HashMap<String, f32>) -> Result<()> {
+        self.robot.set_joints_by_name(&positions)
+    }
+
+    /// Full joint vector (in the order of `movable_joint_names`) of an active one.
+    fn active_to_full<'py>(
+        &self,
+        py: Python<'py>,
+        q: PyArrayLike1<f32, AllowTypeChange>,
+    ) -> Result<Bound<'py, PyArray1<f32>>> {
+        Ok(self
+            .robot
+            .active_to_full(q.as_slice()?)?
+            .into_pyarray_bound(py))
+    }
+
+    /// Active joint vector (in the order of `joint_names`) of a full one.
+    fn full_to_active<'py>(
+        &self,
+        py: Python<'py>,
+        q: PyArrayLike1<f32, AllowTypeChange>,
+    ) -> Result<Bound<'py, PyArray1<f32>>> {
+        Ok(self
+            .robot
+            .full_to_active(q.as_slice()?)?
+            .into_pyarray_bound(py))
+    }
+
+    /// World pose (4x4 matrix) of the robot's root link, or of the origin of its virtual
+    /// base joints.
+    #[getter]
+    fn base_pose<'py>(&self, py: Python<'py>) -> Result<Bound<'py, PyArray2<f32>>> {
+        let pose = self.robot.base_pose();
+        Ok(
+            Array2::from_shape_vec((4, 4), homogeneous_row_major(&pose).to_vec())?
+                .into_pyarray_bound(py),
+        )
+    }
+
+    #[pyo3(signature = (xyz, rpy=[0.0, 0.0, 0.0]))]
+    fn set_base_pose(&mut self, xyz: [f64; 3], rpy: [f64; 3]) {
+        self.robot.set_base_pose(pose_to_isometry(&Pose {
+            xyz: Vec3(xyz),
+            rpy: Vec3(rpy),
+        }));
+    }
+
+    /// Names of the links, in the order of the link poses.
     #[getter]
     fn link_names(&self) -> Vec<String> {
-        // self.robot.name()
-        self.robot
-            .robot_chain
-            .iter_links()
-            .map(|link| link.name.clone())
-            .collect()
+        self.robot.link_names()
+    }
+
+    /// World pose (4x4 matrix) of a link at the given joint positions, which are also set
+    /// on the robot.
+    fn link_pose<'py>(
+        &mut self,
+        py: Python<'py>,
+        q: PyArrayLike1<f32, AllowTypeChange>,
+        link_name: &str,
+    ) -> Result<Bound<'py, PyArray2<f32>>> {
+        let pose = self.robot.link_pose(q.as_slice()?, link_name)?;
+        Ok(
+            Array2::from_shape_vec((4, 4), homogeneous_row_major(&pose).to_vec())?
+                .into_pyarray_bound(py),
+        )
     }
 
-    // #[pyfunction]
-    // fn sum_up<'py>(py: Python<'py>, array: PyArrayLike2<'py, f32, AllowTypeChange>) -> f32 {
+    /// World poses (L x 4 x 4) of every link at the given joint positions, which are also
+    /// set on the robot.
+    fn link_poses<'py>(
+        &mut self,
+        py: Python<'py>,
+        q: PyArrayLike1<f32, AllowTypeChange>,
+    ) -> Result<Bound<'py, PyArrayDyn<f32>>> {
+        let poses = self.robot.link_poses(q.as_slice()?)?;
+        let data = poses.iter().flat_map(homogeneous_row_major).collect();
+        Ok(ArrayD::from_shape_vec(vec![poses.len(), 4, 4], data)?.into_pyarray_bound(py))
+    }
+
+    /// Link poses of every row (a configuration) of the array, computed in parallel and
+    /// without holding the GIL. Returns an N x L x 4 x 4 array of matrices, or with
+    /// `pos_quat` an N x L x 7 array of positions and quaternions (x, y, z, qx, qy, qz, qw).
+    /// Rows that violate the joint limits give NaN poses.
+    #[pyo3(signature = (array, pos_quat=false))]
+    fn link_poses_batch<'py>(
+        &self,
+        py: Python<'py>,
+        array: PyArrayLike2<'py, f32, AllowTypeChange>,
+        pos_quat: bool,
+    ) -> Result<Bound<'py, PyArrayDyn<f32>>> {
+        let array = array.as_array();
+        let (num_configs, dof) = array.dim();
+        // copy, as the array can neither be shared across threads nor be assumed contiguous
+        let configs: Vec<f32> = array.iter().copied().collect();
+
+        let poses = py.allow_threads(|| self.robot.link_poses_batch(&configs, dof))?;
+        let num_links = poses.len() / num_configs.max(1);
+        let array = if pos_quat {
+            let data = poses
+                .iter()
+                .flat_map(|pose| {
+                    let (t, q) = (pose.translation.vector, pose.rotation.coords);
+                    [t.x, t.y, t.z, q.x, q.y, q.z, q.w]
+                })
+                .collect();
+            ArrayD::from_shape_vec(vec![num_configs, num_links, 7], data)?
+        } else {
+            let data = poses.iter().flat_map(homogeneous_row_major).collect();
+            ArrayD::from_shape_vec(vec![num_configs, num_links, 4, 4], data)?
+        };
+        Ok(array.into_pyarray_bound(py))
+    }
+
+    /// Geometric Jacobian (6 x dof, linear velocity rows first) of a link at the given joint
+    /// positions, which are also set on the robot. `frame` is 'world' or 'local'.
+    #[pyo3(signature = (q, link_name, frame="world"))]
+    fn jacobian<'py>(
+        &mut self,
+        py: Python<'py>,
+        q: PyArrayLike1<f32, AllowTypeChange>,
+        link_name: &str,
+        frame: &str,
+    ) -> Result<Bound<'py, PyArray2<f32>>> {
+        let jacobian = self
+            .robot
+            .jacobian(q.as_slice()?, link_name, frame.parse()?)?;
+        Ok(
+            Array2::from_shape_fn(jacobian.shape(), |(r, c)| jacobian[(r, c)])
+                .into_pyarray_bound(py),
+        )
+    }
+
+    /// Jacobians of the link for every row (a configuration) of the array, computed in
+    /// parallel and without holding the GIL. Returns an N x 6 x dof array, NaN for rows that
+    /// violate the joint limits.
+    #[pyo3(signature = (array, link_name, frame="world"))]
+    fn jacobian_batch<'py>(
+        &self,
+        py: Python<'py>,
+        array: PyArrayLike2<'py, f32, AllowTypeChange>,
+        link_name: &str,
+        frame: &str,
+    ) -> Result<Bound<'py, PyArrayDyn<f32>>> {
+        let frame = frame.parse()?;
+        let array = array.as_array();
+        let (num_configs, dof) = array.dim();
+        let configs: Vec<f32> = array.iter().copied().collect();
+
+        let jacobians =
+            py.allow_threads(|| self.robot.jacobian_batch(&configs, dof, link_name, frame))?;
+        // nalgebra matrices are column major
+        let data = jacobians
+            .iter()
+            .flat_map(|jacobian| jacobian.transpose().as_slice().to_vec())
+            .collect();
+        Ok(ArrayD::from_shape_vec(vec![num_configs, 6, dof], data)?.into_pyarray_bound(py))
+    }
+
+    /// Manipulability metrics of a link at the given joint positions, which are also set on
+    /// the robot, as a dict with 'manipulability', 'condition_number' and
+    /// 'min_singular_value' (the distance to the closest singularity).
+    fn manipulability<'py>(
+        &mut self,
+        py: Python<'py>,
+        q: PyArrayLike1<f32, AllowTypeChange>,
+        link_name: &str,
+    ) -> Result<Bound<'py, PyDict>> {
+        let metrics = self.robot.manipulability(q.as_slice()?, link_name)?;
+        let dict = PyDict::new_bound(py);
+        dict.set_item("manipulability", metrics.manipulability)?;
+        dict.set_item("condition_number", metrics.condition_number)?;
+        dict.set_item("min_singular_value", metrics.min_singular_value)?;
+        Ok(dict)
+    }
+
+    /// Manipulability metrics of the link for every row (a configuration) of the array.
+    /// Returns an N x 3 array of (manipulability, condition number, min singular value),
+    /// NaN for rows that violate the joint limits.
+    fn manipulability_batch<'py>(
+        &self,
+        py: Python<'py>,
+        array: PyArrayLike2<'py, f32, AllowTypeChange>,
+        link_name: &str,
+    ) -> Result<Bound<'py, PyArray2<f32>>> {
+        let array = array.as_array();
+        let (num_configs, dof) = array.dim();
+        let configs: Vec<f32> = array.iter().copied().collect();
+
+        let data = py.allow_threads(|| -> Result<Vec<f32>> {
+            let metrics = self.robot.manipulability_batch(&configs, dof, link_name)?;
+            Ok(metrics
+                .iter()
+                .flat_map(|metrics| {
+                    [
+                        metrics.manipulability,
+                        metrics.condition_number,
+                        metrics.min_singular_value,
+                    ]
+                })
+                .collect())
+        })?;
+        Ok(Array2::from_shape_vec((num_configs, 3), data)?.into_pyarray_bound(py))
+    }
 
     fn set_joints(&mut self, array: PyArrayLike1<f32, AllowTypeChange>) -> Result<()> {
         self.robot.set_joints(array.as_slice()?)
@@ -102,41 +350,105 @@ impl PyRobot {
         self.robot.has_collision().map(|result| result.into())
     }
 
-    fn get_colliding_pairs(&mut self) -> Vec<(String, String)> {
-        dbg!(self.robot.collision_checker.get_colliding_pairs());
-        dbg!(self.robot.collision_checker.print_collision_info());
+    /// Same as `is_colliding`, but stops at the first collision found. The colliding pairs
+    /// and the collision report are not updated.
+    fn is_colliding_fast(&mut self) -> Result<bool> {
+        self.robot.has_collision_fast().map(|result| result.into())
+    }
 
-        let collider_mappings: HashMap<ColliderHandle, String> = self
-            .robot
-            .colliders
-            .iter()
-            .flat_map(|(link_name, collidre_handles)| {
-                collidre_handles
-                    .iter()
-                    .map(|(handle)| (*handle, link_name.clone()))
-            })
-            .collect();
+    /// Padding of the links without their own padding.
+    #[getter]
+    fn get_padding(&self) -> f32 {
+        self.robot.padding()
+    }
+
+    #[setter]
+    fn set_padding(&mut self, padding: f32) -> Result<()> {
+        self.robot.set_padding(padding)
+    }
+
+    /// Links with their own padding.
+    #[getter]
+    fn link_paddings(&self) -> HashMap<String, f32> {
+        self.robot.link_paddings().clone()
+    }
+
+    /// Sets the padding of a single link, or reverts it to the global padding with `None`.
+    #[pyo3(signature = (link_name, padding=None))]
+    fn set_link_padding(&mut self, link_name: &str, padding: Option<f32>) -> Result<()> {
+        self.robot.set_link_padding(link_name, padding)
+    }
+
+    /// Enables or disables every collision of the link.
+    fn set_link_collision_enabled(&mut self, link_name: &str, enabled: bool) -> Result<()> {
+        self.robot.set_link_collision_enabled(link_name, enabled)
+    }
+
+    fn is_link_collision_enabled(&self, link_name: &str) -> Result<bool> {
+        self.robot.is_link_collision_enabled(link_name)
+    }
 
-        // self.robot.collision_checker.print_collision_info();
-        // self.robot.collision_checker.print_collision_info();
-        // self.robot.collision_checker.print_collision_info();
-        // dbg!(self.robot.collision_checker.get_colliding_pairs());
-        // self.robot.collision_checker.print_collision_info();
+    /// Ignores collisions between the two links.
+    fn allow_collision(&mut self, link_a: &str, link_b: &str) -> Result<()> {
+        self.robot.allow_collision(link_a, link_b)
+    }
+
+    /// Checks collisions between the two links again.
+    fn disallow_collision(&mut self, link_a: &str, link_b: &str) -> Result<()> {
+        self.robot.disallow_collision(link_a, link_b)
+    }
 
+    /// Links whose visual and collision bounding boxes differ by more than `tolerance`,
+    /// together with the largest difference.
+    #[pyo3(signature = (tolerance=0.01))]
+    fn check_geometry_consistency(&self, tolerance: f32) -> Result<Vec<(String, f32)>> {
+        Ok(self
+            .robot
+            .check_geometry_consistency(tolerance)?
+            .into_iter()
+            .map(|mismatch| (mismatch.link, mismatch.difference))
+            .collect())
+    }
 
+    /// Names of the colliding pairs found by the last collision check.
+    fn get_colliding_pairs(&self) -> Vec<(String, String)> {
         self.robot
-            .collision_checker
-            .get_colliding_pairs()
-            .iter()
-            .map(|(a, b)| {
-                (
-                    collider_mappings.get(a).unwrap().clone(),
-                    collider_mappings.get(b).unwrap().clone(),
-                )
-            })
+            .collision_report()
+            .pairs
+            .into_iter()
+            .map(|pair| (pair.name1, pair.name2))
             .collect()
+    }
 
-        // self.robot.has_collision(&self.robot.robot_chain.joint_positions()).map(|result| result.into())
+    /// The collisions found by the last collision check, as one dict per colliding pair.
+    fn collision_report<'py>(&self, py: Python<'py>) -> Result<Vec<Bound<'py, PyDict>>> {
+        self.robot
+            .collision_report()
+            .pairs
+            .into_iter()
+            .map(|pair| {
+                let contacts = pair
+                    .contacts
+                    .iter()
+                    .map(|contact| {
+                        let dict = PyDict::new_bound(py);
+                        dict.set_item("point1", contact.point1.coords.as_slice().to_vec())?;
+                        dict.set_item("point2", contact.point2.coords.as_slice().to_vec())?;
+                        dict.set_item("normal", contact.normal.as_slice().to_vec())?;
+                        dict.set_item("distance", contact.distance)?;
+                        Ok(dict)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                let dict = PyDict::new_bound(py);
+                dict.set_item("name1", pair.name1)?;
+                dict.set_item("name2", pair.name2)?;
+                dict.set_item("is_environment", pair.kind == ContactKind::Environment)?;
+                dict.set_item("depth", pair.depth)?;
+                dict.set_item("contacts", contacts)?;
+                Ok(dict)
+            })
+            .collect()
     }
 
     fn has_collision(&mut self, array: PyArrayLike2<f32, AllowTypeChange>) -> Result<Vec<bool>> {
@@ -165,27 +477,628 @@ impl PyRobot {
             .collect()
     }
 
+    /// Checks every row of the array for collision, in parallel and without holding the GIL.
+    fn has_collision_batch<'py>(
+        &self,
+        py: Python<'py>,
+        array: PyArrayLike2<'py, f32, AllowTypeChange>,
+    ) -> Result<Bound<'py, PyArray1<bool>>> {
+        let array = array.as_array();
+        let dof = array.ncols();
+        // copy, as the array can neither be shared across threads nor be assumed contiguous
+        let configs: Vec<f32> = array.iter().copied().collect();
+
+        let results = py.allow_threads(|| self.robot.has_collision_batch(&configs, dof))?;
+        Ok(Array1::from_iter(results.into_iter().map(Into::<bool>::into)).into_pyarray_bound(py))
+    }
+
+    /// Joint positions for which the link reaches the target in the world frame, which are
+    /// also set on the robot. Returns None if no solution was found.
+    ///
+    /// With `mode="position"`, only `xyz` is reached and the orientation is free. With
+    /// `mode="pose"`, the orientation `rpy` is reached too, within `angle_tolerance`. By
+    /// default, the mode is "pose" if `rpy` is given and "position" otherwise.
+    #[pyo3(signature = (
+        link_name, xyz, rpy=None, mode=None, position_tolerance=0.001, angle_tolerance=0.005,
+        max_iterations=100, num_restarts=10, seed=None, reject_colliding=false, rng_seed=42
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn inverse_kinematics<'py>(
+        &mut self,
+        py: Python<'py>,
+        link_name: &str,
+        xyz: [f64; 3],
+        rpy: Option<[f64; 3]>,
+        mode: Option<&str>,
+        position_tolerance: f32,
+        angle_tolerance: f32,
+        max_iterations: usize,
+        num_restarts: usize,
+        seed: Option<PyArrayLike1<f32, AllowTypeChange>>,
+        reject_colliding: bool,
+        rng_seed: u64,
+    ) -> Result<Option<Bound<'py, PyArray1<f32>>>> {
+        let mode = mode.unwrap_or(if rpy.is_some() { "pose" } else { "position" });
+        let target = match (mode, rpy) {
+            ("position", None) => IkTarget::Position(xyz.map(|v| v as f32).into()),
+            ("position", Some(_)) => {
+                eyre::bail!("rpy cannot be given for a position target (mode='position')")
+            }
+            ("pose", Some(rpy)) => IkTarget::Pose {
+                pose: pose_to_isometry(&Pose {
+                    xyz: Vec3(xyz),
+                    rpy: Vec3(rpy),
+                }),
+                angle_tolerance,
+            },
+            ("pose", None) => eyre::bail!("rpy is needed for a pose target (mode='pose')"),
+            _ => eyre::bail!("Unknown IK mode '{}' (expected 'position' or 'pose')", mode),
+        };
+        let option = IkOption {
+            position_tolerance,
+            max_iterations,
+            num_restarts,
+            seed: seed.map(|seed| seed.as_array().to_vec()),
+            reject_colliding,
+            rng_seed,
+        };
+        Ok(self
+            .robot
+            .inverse_kinematics(link_name, &target, &option)?
+            .map(|joints| joints.into_pyarray_bound(py)))
+    }
+
+    #[getter]
+    fn planning_groups(&self) -> Vec<String> {
+        self.robot
+            .srdf
+            .groups
+            .iter()
+            .map(|group| group.name.clone())
+            .collect()
+    }
+
+    fn group_joint_names(&self, group: &str) -> Result<Vec<String>> {
+        self.robot.group_joint_names(group)
+    }
+
+    fn group_state(&self, group: &str, name: &str) -> Result<HashMap<String, f32>> {
+        let state = self.robot.srdf.group_state(group, name).ok_or_else(|| {
+            RobotError::UnknownGroupState {
+                group: group.to_owned(),
+                name: name.to_owned(),
+            }
+        })?;
+        Ok(state.joint_positions()?.into_iter().collect())
+    }
+
+    /// end effectors as (name, group, parent_link, parent_group)
+    #[getter]
+    fn end_effectors(&self) -> Vec<(String, String, String, Option<String>)> {
+        self.robot
+            .srdf
+            .end_effectors
+            .iter()
+            .map(|ee| {
+                (
+                    ee.name.clone(),
+                    ee.group.clone(),
+                    ee.parent_link.clone(),
+                    ee.parent_group.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Checks the straight joint-space motion between two configurations. Returns the
+    /// fraction of the motion where the first collision happens, or None if it is free.
+    fn check_motion(
+        &mut self,
+        q_from: PyArrayLike1<f32, AllowTypeChange>,
+        q_to: PyArrayLike1<f32, AllowTypeChange>,
+    ) -> Result<Option<f32>> {
+        self.robot
+            .check_motion(q_from.as_slice()?, q_to.as_slice()?)
+    }
+
+    /// Minimum signed distance between every checked pair of links (and link/obstacle
+    /// pair), as a dict of numpy arrays.
+    fn distance_report<'py>(&mut self, py: Python<'py>) -> Result<Bound<'py, PyDict>> {
+        let report = self.robot.distance_report()?;
+        let n = report.pairs.len();
+
+        let flatten = |f: fn(&robotsim::robot::PairDistance) -> [f32; 3]| {
+            Array2::from_shape_vec((n, 3), report.pairs.iter().flat_map(f).collect())
+        };
+
+        let dict = PyDict::new_bound(py);
+        dict.set_item(
+            "names1",
+            report
+                .pairs
+                .iter()
+                .map(|p| p.name1.clone())
+                .collect::<Vec<_>>(),
+        )?;
+        dict.set_item(
+            "names2",
+            report
+                .pairs
+                .iter()
+                .map(|p| p.name2.clone())
+                .collect::<Vec<_>>(),
+        )?;
+        dict.set_item(
+            "is_environment",
+            Array1::from_iter(
+                report
+                    .pairs
+                    .iter()
+                    .map(|p| p.kind == ContactKind::Environment),
+            )
+            .into_pyarray_bound(py),
+        )?;
+        dict.set_item(
+            "distances",
+            Array1::from_iter(report.pairs.iter().map(|p| p.distance)).into_pyarray_bound(py),
+        )?;
+        dict.set_item(
+            "points1",
+            flatten(|p| p.point1.coords.into())?.into_pyarray_bound(py),
+        )?;
+        dict.set_item(
+            "points2",
+            flatten(|p| p.point2.coords.into())?.into_pyarray_bound(py),
+        )?;
+        dict.set_item(
+            "normals",
+            flatten(|p| p.normal.into())?.into_pyarray_bound(py),
+        )?;
+        Ok(dict)
+    }
+
+    /// Samples random configurations to find link pairs that can be excluded from
+    /// collision checking. Returns (link1, link2, reason) triplets. With
+    /// `srdf_output_path`, the pairs are also written there as an srdf document.
+    #[pyo3(signature = (num_samples=10_000, min_collision_fraction=0.95, seed=42, apply=true, srdf_output_path=None))]
+    fn generate_disabled_collisions(
+        &mut self,
+        num_samples: usize,
+        min_collision_fraction: f32,
+        seed: u64,
+        apply: bool,
+        srdf_output_path: Option<&str>,
+    ) -> Result<Vec<(String, String, String)>> {
+        let pairs = self
+            .robot
+            .generate_disabled_collisions(&CollisionMatrixGenerationOption {
+                num_samples,
+                min_collision_fraction,
+                seed,
+            })?;
+
+        if let Some(path) = srdf_output_path {
+            std::fs::write(
+                path,
+                disable_collisions_to_srdf(&self.robot.urdf_robot.name, &pairs),
+            )?;
+        }
+        if apply {
+            self.robot.apply_disabled_collisions(&pairs);
+        }
+
+        Ok(pairs
+            .into_iter()
+            .map(|pair| (pair.link1, pair.link2, pair.reason.unwrap_or_default()))
+            .collect())
+    }
+
+    /// Closest hit of a ray against the links, attached objects and obstacles, as a dict
+    /// (distance, point, normal, name), or None if nothing is hit.
+    #[pyo3(signature = (origin, direction, max_distance=f32::INFINITY))]
+    fn cast_ray<'py>(
+        &mut self,
+        py: Python<'py>,
+        origin: [f32; 3],
+        direction: [f32; 3],
+        max_distance: f32,
+    ) -> Result<Option<Bound<'py, PyDict>>> {
+        let Some(hit) = self
+            .robot
+            .cast_ray(origin.into(), direction.into(), max_distance)?
+        else {
+            return Ok(None);
+        };
+        let dict = PyDict::new_bound(py);
+        dict.set_item("distance", hit.distance)?;
+        dict.set_item("point", hit.point.coords.as_slice().to_vec())?;
+        dict.set_item("normal", hit.normal.as_slice().to_vec())?;
+        dict.set_item("name", hit.name)?;
+        Ok(Some(dict))
+    }
+
+    /// Casts one ray per row of the (N, 3) origin and direction arrays. Returns the hit
+    /// distances (inf for a miss), the (N, 3) normals (zero for a miss) and the names of
+    /// what was hit (None for a miss).
+    #[pyo3(signature = (origins, directions, max_distance=f32::INFINITY))]
+    fn cast_rays<'py>(
+        &mut self,
+        py: Python<'py>,
+        origins: PyArrayLike2<'py, f32, AllowTypeChange>,
+        directions: PyArrayLike2<'py, f32, AllowTypeChange>,
+        max_distance: f32,
+    ) -> Result<(
+        Bound<'py, PyArray1<f32>>,
+        Bound<'py, PyArray2<f32>>,
+        Vec<Option<String>>,
+    )> {
+        let (origins, directions) = (origins.as_array(), directions.as_array());
+        ensure!(
+            origins.ncols() == 3 && origins.dim() == directions.dim(),
+            "Expected origins and directions of the same (N, 3) shape, got {:?} and {:?}",
+            origins.dim(),
+            directions.dim()
+        );
+        let rays: Vec<_> = origins
+            .rows()
+            .into_iter()
+            .zip(directions.rows())
+            .map(|(origin, dir)| {
+                (
+                    [origin[0], origin[1], origin[2]].into(),
+                    [dir[0], dir[1], dir[2]].into(),
+                )
+            })
+            .collect();
+
+        let hits = py.allow_threads(|| self.robot.cast_rays(&rays, max_distance))?;
+
+        let distances = hits
+            .iter()
+            .map(|hit| hit.as_ref().map_or(f32::INFINITY, |hit| hit.distance));
+        let normals = hits.iter().flat_map(|hit| {
+            hit.as_ref()
+                .map_or([0.0; 3], |hit| [hit.normal.x, hit.normal.y, hit.normal.z])
+        });
+        let names = hits
+            .iter()
+            .map(|hit| hit.as_ref().map(|hit| hit.name.clone()));
+        Ok((
+            Array1::from_iter(distances).into_pyarray_bound(py),
+            Array2::from_shape_vec((hits.len(), 3), normals.collect())?.into_pyarray_bound(py),
+            names.collect(),
+        ))
+    }
+
+    #[getter]
+    fn obstacle_names(&self) -> Vec<String> {
+        self.robot.obstacles.keys().cloned().collect()
+    }
+
+    #[pyo3(signature = (name, size, xyz=[0.0; 3], rpy=[0.0; 3]))]
+    fn add_box_obstacle(
+        &mut self,
+        name: &str,
+        size: [f64; 3],
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    ) -> Result<()> {
+        self.robot.add_obstacle(
+            name,
+            &Geometry::Box { size: Vec3(size) },
+            pose_to_isometry(&Pose {
+                xyz: Vec3(xyz),
+                rpy: Vec3(rpy),
+            }),
+        )
+    }
+
+    #[pyo3(signature = (name, radius, xyz=[0.0; 3], rpy=[0.0; 3]))]
+    fn add_sphere_obstacle(
+        &mut self,
+        name: &str,
+        radius: f64,
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    ) -> Result<()> {
+        self.robot.add_obstacle(
+            name,
+            &Geometry::Sphere { radius },
+            pose_to_isometry(&Pose {
+                xyz: Vec3(xyz),
+                rpy: Vec3(rpy),
+            }),
+        )
+    }
+
+    #[pyo3(signature = (name, radius, length, xyz=[0.0; 3], rpy=[0.0; 3]))]
+    fn add_cylinder_obstacle(
+        &mut self,
+        name: &str,
+        radius: f64,
+        length: f64,
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    ) -> Result<()> {
+        self.robot.add_obstacle(
+            name,
+            &Geometry::Cylinder { radius, length },
+            pose_to_isometry(&Pose {
+                xyz: Vec3(xyz),
+                rpy: Vec3(rpy),
+            }),
+        )
+    }
+
+    #[pyo3(signature = (name, radius, length, xyz=[0.0; 3], rpy=[0.0; 3]))]
+    fn add_capsule_obstacle(
+        &mut self,
+        name: &str,
+        radius: f64,
+        length: f64,
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    ) -> Result<()> {
+        self.robot.add_obstacle(
+            name,
+            &Geometry::Capsule { radius, length },
+            pose_to_isometry(&Pose {
+                xyz: Vec3(xyz),
+                rpy: Vec3(rpy),
+            }),
+        )
+    }
+
+    #[pyo3(signature = (name, filename, scale=None, xyz=[0.0; 3], rpy=[0.0; 3]))]
+    fn add_mesh_obstacle(
+        &mut self,
+        name: &str,
+        filename: String,
+        scale: Option<[f64; 3]>,
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    ) -> Result<()> {
+        self.robot.add_obstacle(
+            name,
+            &Geometry::Mesh {
+                filename,
+                scale: scale.map(Vec3),
+            },
+            pose_to_isometry(&Pose {
+                xyz: Vec3(xyz),
+                rpy: Vec3(rpy),
+            }),
+        )
+    }
+
+    /// Adds an (N, 3) array of points as an obstacle made of balls of the given radius.
+    /// Returns the number of points kept after filtering.
+    #[pyo3(signature = (
+        name, points, radius=0.01, voxel_size=None, remove_robot_points=false,
+        robot_margin=0.02, xyz=[0.0; 3], rpy=[0.0; 3]
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_point_cloud_obstacle(
+        &mut self,
+        name: &str,
+        points: PyArrayLike2<f32, AllowTypeChange>,
+        radius: f32,
+        voxel_size: Option<f32>,
+        remove_robot_points: bool,
+        robot_margin: f32,
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    ) -> Result<usize> {
+        let points = points.as_array();
+        ensure!(
+            points.ncols() == 3,
+            "Expected an (N, 3) array of points, got {:?}",
+            points.dim()
+        );
+        let points: Vec<_> = points
+            .rows()
+            .into_iter()
+            .map(|p| [p[0], p[1], p[2]].into())
+            .collect();
+        self.robot.add_point_cloud_obstacle(
+            name,
+            &points,
+            pose_to_isometry(&Pose {
+                xyz: Vec3(xyz),
+                rpy: Vec3(rpy),
+            }),
+            &PointCloudOption {
+                radius,
+                voxel_size,
+                remove_robot_points,
+                robot_margin,
+            },
+        )
+    }
+
+    /// Same as `add_point_cloud_obstacle`, with the points read from a PLY or PCD file.
+    #[pyo3(signature = (
+        name, filename, radius=0.01, voxel_size=None, remove_robot_points=false,
+        robot_margin=0.02, xyz=[0.0; 3], rpy=[0.0; 3]
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn add_point_cloud_obstacle_from_file(
+        &mut self,
+        name: &str,
+        filename: &str,
+        radius: f32,
+        voxel_size: Option<f32>,
+        remove_robot_points: bool,
+        robot_margin: f32,
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    ) -> Result<usize> {
+        self.robot.add_point_cloud_obstacle_from_file(
+            name,
+            filename,
+            pose_to_isometry(&Pose {
+                xyz: Vec3(xyz),
+                rpy: Vec3(rpy),
+            }),
+            &PointCloudOption {
+                radius,
+                voxel_size,
+                remove_robot_points,
+                robot_margin,
+            },
+        )
+    }
+
+    /// Adds a 3D boolean occupancy array as an obstacle. Voxel [i, j, k] is the cube of
+    /// side `voxel_size` at (i, j, k) * voxel_size, in the frame given by xyz/rpy.
+    #[pyo3(signature = (name, occupancy, voxel_size, xyz=[0.0; 3], rpy=[0.0; 3]))]
+    fn add_voxel_grid_obstacle(
+        &mut self,
+        name: &str,
+        occupancy: PyArrayLike3<bool, AllowTypeChange>,
+        voxel_size: f32,
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    ) -> Result<()> {
+        let occupancy = occupancy.as_array();
+        let (nx, ny, nz) = occupancy.dim();
+        self.robot.add_voxel_grid_obstacle(
+            name,
+            [nx, ny, nz],
+            voxel_size,
+            &occupancy.iter().copied().collect::<Vec<_>>(),
+            pose_to_isometry(&Pose {
+                xyz: Vec3(xyz),
+                rpy: Vec3(rpy),
+            }),
+        )
+    }
+
+    /// Sets the occupancy of the voxels given by the rows of the (N, 3) index array.
+    fn set_voxels(
+        &mut self,
+        name: &str,
+        indices: PyArrayLike2<usize, AllowTypeChange>,
+        occupied: PyArrayLike1<bool, AllowTypeChange>,
+    ) -> Result<()> {
+        let (indices, occupied) = (indices.as_array(), occupied.as_array());
+        ensure!(
+            indices.ncols() == 3 && indices.nrows() == occupied.len(),
+            "Expected an (N, 3) index array and N occupancy values, got {:?} and {}",
+            indices.dim(),
+            occupied.len()
+        );
+        let voxels: Vec<_> = indices
+            .rows()
+            .into_iter()
+            .zip(occupied.iter())
+            .map(|(idx, occupied)| ([idx[0], idx[1], idx[2]], *occupied))
+            .collect();
+        self.robot.set_voxels(name, &voxels)
+    }
+
+    #[pyo3(signature = (name, xyz=[0.0; 3], rpy=[0.0; 3]))]
+    fn set_obstacle_pose(&mut self, name: &str, xyz: [f64; 3], rpy: [f64; 3]) -> Result<()> {
+        self.robot.set_obstacle_pose(
+            name,
+            pose_to_isometry(&Pose {
+                xyz: Vec3(xyz),
+                rpy: Vec3(rpy),
+            }),
+        )
+    }
+
+    fn remove_obstacle(&mut self, name: &str) -> Result<()> {
+        self.robot.remove_obstacle(name)
+    }
+
+    /// Attaches an object to a link. `shape` is one of "box" (with `size`), "sphere"
+    /// (with `radius`), "cylinder" or "capsule" (with `radius` and `length`), or "mesh"
+    /// (with `filename` and optionally `scale`). The object may touch `link` and the
+    /// `touch_links` without being in collision.
+    #[pyo3(signature = (
+        name, link, shape, size=None, radius=None, length=None, filename=None, scale=None,
+        xyz=[0.0; 3], rpy=[0.0; 3], touch_links=Vec::new()
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn attach_object(
+        &mut self,
+        name: &str,
+        link: &str,
+        shape: &str,
+        size: Option<[f64; 3]>,
+        radius: Option<f64>,
+        length: Option<f64>,
+        filename: Option<String>,
+        scale: Option<[f64; 3]>,
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+        touch_links: Vec<String>,
+    ) -> Result<()> {
+        let missing = |arg: &str| eyre::eyre!("A '{}' object needs a '{}'", shape, arg);
+        let geometry = match shape {
+            "box" => Geometry::Box {
+                size: Vec3(size.ok_or_else(|| missing("size"))?),
+            },
+            "sphere" => Geometry::Sphere {
+                radius: radius.ok_or_else(|| missing("radius"))?,
+            },
+            "cylinder" => Geometry::Cylinder {
+                radius: radius.ok_or_else(|| missing("radius"))?,
+                length: length.ok_or_else(|| missing("length"))?,
+            },
+            "capsule" => Geometry::Capsule {
+                radius: radius.ok_or_else(|| missing("radius"))?,
+                length: length.ok_or_else(|| missing("length"))?,
+            },
+            "mesh" => Geometry::Mesh {
+                filename: filename.ok_or_else(|| missing("filename"))?,
+                scale: scale.map(Vec3),
+            },
+            _ => eyre::bail!("Unknown shape '{}'", shape),
+        };
+
+        let touch_links: Vec<&str> = touch_links.iter().map(String::as_str).collect();
+        self.robot.attach_object(
+            name,
+            link,
+            &geometry,
+            pose_to_isometry(&Pose {
+                xyz: Vec3(xyz),
+                rpy: Vec3(rpy),
+            }),
+            &touch_links,
+        )
+    }
+
+    fn detach_object(&mut self, name: &str) -> Result<()> {
+        self.robot.detach_object(name).map(|_| ())
+    }
+
+    #[getter]
+    fn attached_object_names(&self) -> Vec<String> {
+        self.robot.attached_objects.keys().cloned().collect()
+    }
+
     fn __repr__(&self) -> String {
         format!("<Robot '{}'>", self.name())
     }
 }
 
 use eyre::OptionExt;
-use numpy::{get_array_module, AllowTypeChange, PyArrayLike2};
+use numpy::{get_array_module, AllowTypeChange, PyArrayLike2, PyArrayLike3};
 
 #[pyfunction]
-fn sum_up<'py>(py: Python<'py>, array: PyArrayLike2<'py, f32, AllowTypeChange>) -> f32 {
-    array.as_array().rows().into_iter().for_each(|row| {
-        let a = row.as_slice();
-
-        dbg!(a.ok_or_eyre("Failed to get slice (array is not contiguous?)")).unwrap();
-        println!("{:?}", row);
-    });
-
-    dbg!(array.as_slice().unwrap());
+fn sum_up<'py>(array: PyArrayLike2<'py, f32, AllowTypeChange>) -> f32 {
     array.as_array().sum()
 }
 
+/// Drops the shapes cached from mesh files, e.g. after the files were modified on disk.
+#[pyfunction]
+fn clear_mesh_shape_cache() {
+    robotsim::robot::clear_mesh_shape_cache();
+}
+
 #[pyfunction]
 fn double(x: usize) -> usize {
     x * 2
@@ -205,6 +1118,12 @@ mod py_robotsim {
     #[pymodule_export]
     use super::PyRobot;
 
+    #[pymodule_export]
+    use super::clear_mesh_shape_cache;
+
+    #[pymodule_export]
+    use super::scene::PyRobotScene;
+
     #[pyfunction] // This will be part of the module
     fn triple(x: usize) -> usize {
         x * 3
@@ -0,0 +1,128 @@
+use eyre::Result;
+use numpy::{AllowTypeChange, PyArrayLike1, PyArrayLike2};
+use pyo3::prelude::*;
+use robotsim::robot::{pose_to_isometry, RobotScene, SceneCollisionResult};
+use urdf_rs::{Geometry, Pose, Vec3};
+
+use super::PyRobot;
+
+fn xyz_rpy_to_pose(xyz: [f64; 3], rpy: [f64; 3]) -> Pose {
+    Pose {
+        xyz: Vec3(xyz),
+        rpy: Vec3(rpy),
+    }
+}
+
+/// Several robots in one world, checked against each other.
+#[pyclass(module = "robotsim", name = "RobotScene")]
+pub(crate) struct PyRobotScene {
+    scene: RobotScene,
+}
+
+#[pymethods]
+impl PyRobotScene {
+    #[new]
+    fn py_new() -> Self {
+        Self {
+            scene: RobotScene::new(),
+        }
+    }
+
+    /// Adds a copy of the robot to the scene, at the given base pose.
+    #[pyo3(signature = (name, robot, xyz=[0.0; 3], rpy=[0.0; 3]))]
+    fn add_robot(
+        &mut self,
+        name: String,
+        robot: PyRef<PyRobot>,
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    ) -> Result<()> {
+        self.scene.add_robot(
+            name,
+            robot.robot.clone(),
+            pose_to_isometry(&xyz_rpy_to_pose(xyz, rpy)),
+        )
+    }
+
+    fn remove_robot(&mut self, name: String) -> Result<()> {
+        self.scene.remove_robot(&name).map(|_| ())
+    }
+
+    #[getter]
+    fn robot_names(&self) -> Vec<String> {
+        self.scene.robots().map(|entry| entry.key.clone()).collect()
+    }
+
+    #[pyo3(signature = (name, xyz=[0.0; 3], rpy=[0.0; 3]))]
+    fn set_base_pose(&mut self, name: String, xyz: [f64; 3], rpy: [f64; 3]) -> Result<()> {
+        self.scene
+            .set_base_pose(&name, pose_to_isometry(&xyz_rpy_to_pose(xyz, rpy)))
+    }
+
+    fn set_self_collision(&mut self, name: String, enabled: bool) -> Result<()> {
+        self.scene.set_self_collision(&name, enabled)
+    }
+
+    #[pyo3(signature = (name, size, xyz=[0.0; 3], rpy=[0.0; 3]))]
+    fn add_box_obstacle(
+        &mut self,
+        name: &str,
+        size: [f64; 3],
+        xyz: [f64; 3],
+        rpy: [f64; 3],
+    ) -> Result<()> {
+        self.scene.add_obstacle(
+            name,
+            &Geometry::Box { size: Vec3(size) },
+            pose_to_isometry(&xyz_rpy_to_pose(xyz, rpy)),
+        )
+    }
+
+    fn remove_obstacle(&mut self, name: &str) -> Result<()> {
+        self.scene.remove_obstacle(name)
+    }
+
+    #[getter]
+    fn dof(&self) -> usize {
+        self.scene.dof()
+    }
+
+    /// The combined joint vector, i.e. the joints of every robot in the order they were added.
+    #[getter]
+    fn joints(&self) -> Vec<f32> {
+        self.scene.joints()
+    }
+
+    fn set_joints(&mut self, array: PyArrayLike1<f32, AllowTypeChange>) -> Result<()> {
+        self.scene.set_joints(array.as_slice()?)
+    }
+
+    fn is_colliding(&mut self) -> Result<bool> {
+        self.scene.has_collision().map(Into::into)
+    }
+
+    /// Checks every row (a combined joint vector) of the array for collision.
+    fn has_collision(&mut self, array: PyArrayLike2<f32, AllowTypeChange>) -> Result<Vec<bool>> {
+        let array = array.as_array();
+        array
+            .rows()
+            .into_iter()
+            .map(|row| {
+                let joints: Vec<f32> = row.iter().copied().collect();
+                self.scene
+                    .collision_at(&joints)
+                    .map(|result: SceneCollisionResult<String>| result.into())
+            })
+            .collect()
+    }
+
+    /// Colliding (robot, link, robot, link) between different robots, as of the last check.
+    fn inter_robot_colliding_pairs(&self) -> Result<Vec<(String, String, String, String)>> {
+        Ok(self
+            .scene
+            .inter_robot_colliding_pairs()?
+            .into_iter()
+            .map(|contact| (contact.robot1, contact.name1, contact.robot2, contact.name2))
+            .collect())
+    }
+}
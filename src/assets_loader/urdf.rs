@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use bevy::{
-    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    asset::{io::file::FileAssetReader, io::Reader, AssetLoader, AsyncReadExt, LoadContext},
     prelude::*,
     reflect::TypePath,
     render::{
@@ -51,6 +51,8 @@ pub(crate) struct UrdfAsset {
     #[allow(dead_code)]
     pub robot: Robot,
     pub meshes_and_materials: MeshMaterialMapping,
+    /// directory of the urdf file on disk, for loading its collision meshes
+    pub base_dir: Option<String>,
     // pub meshes_and_materials: Vec<(
     //     urdf_rs::Geometry,
     //     Option<Vec<(Mesh, Option<StandardMaterial>)>>,
@@ -259,9 +261,19 @@ impl AssetLoader for UrdfAssetLoader {
                 .await;
             }
 
+            // asset paths are relative to the assets folder (absolute paths stay as-is)
+            let base_dir = base_dir.map(|dir| {
+                FileAssetReader::get_base_path()
+                    .join(AssetPlugin::default().file_path)
+                    .join(dir.path())
+                    .to_string_lossy()
+                    .into_owned()
+            });
+
             Ok(UrdfAsset {
                 robot: urdf_robot,
                 meshes_and_materials,
+                base_dir,
             })
         } else {
             Err(CustomAssetLoaderError::ParsingError)
@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet};
+
+use rapier3d::prelude::{PairFilterContext, PhysicsHooks, SolverFlags};
+
+use super::ColliderOwner;
+
+/// Pairs of links whose collisions are ignored, links whose collisions are ignored
+/// altogether, and the links that each attached object is allowed to touch.
+///
+/// This is applied through rapier's contact pair filtering, so (unlike one collision
+/// group per link) it scales to any number of links.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllowedCollisionMatrix {
+    allowed: HashSet<(usize, usize)>,
+    /// links that do not collide with anything
+    disabled_links: HashSet<usize>,
+    /// attached object id to the links it is allowed to touch
+    touch_links: HashMap<usize, HashSet<usize>>,
+}
+
+fn ordered(a: usize, b: usize) -> (usize, usize) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+impl AllowedCollisionMatrix {
+    /// Ignore collisions between the two links.
+    pub fn allow(&mut self, link_a: usize, link_b: usize) {
+        self.allowed.insert(ordered(link_a, link_b));
+    }
+
+    /// Check collisions between the two links again.
+    pub fn disallow(&mut self, link_a: usize, link_b: usize) {
+        self.allowed.remove(&ordered(link_a, link_b));
+    }
+
+    pub fn is_allowed(&self, link_a: usize, link_b: usize) -> bool {
+        self.allowed.contains(&ordered(link_a, link_b))
+    }
+
+    pub fn allowed_pairs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.allowed.iter().copied()
+    }
+
+    /// Enables or disables every collision of the link, with other links, obstacles and
+    /// attached objects alike. Objects attached to the link keep colliding.
+    pub fn set_link_enabled(&mut self, link: usize, enabled: bool) {
+        if enabled {
+            self.disabled_links.remove(&link);
+        } else {
+            self.disabled_links.insert(link);
+        }
+    }
+
+    pub fn is_link_enabled(&self, link: usize) -> bool {
+        !self.disabled_links.contains(&link)
+    }
+
+    /// Clears the allowed link pairs. Disabled links and touch links of attached objects
+    /// are kept.
+    pub fn clear(&mut self) {
+        self.allowed.clear();
+    }
+
+    /// Ignore collisions between the attached object and the given links.
+    pub fn set_touch_links(&mut self, object_id: usize, links: impl IntoIterator<Item = usize>) {
+        self.touch_links
+            .insert(object_id, links.into_iter().collect());
+    }
+
+    pub fn remove_touch_links(&mut self, object_id: usize) {
+        self.touch_links.remove(&object_id);
+    }
+
+    pub fn is_touch_link(&self, object_id: usize, link: usize) -> bool {
+        self.touch_links
+            .get(&object_id)
+            .is_some_and(|links| links.contains(&link))
+    }
+
+    /// Whether contacts between colliders of the two owners should be computed.
+    pub fn can_collide(&self, owner1: ColliderOwner, owner2: ColliderOwner) -> bool {
+        let is_disabled =
+            |owner| matches!(owner, ColliderOwner::Link(link) if !self.is_link_enabled(link));
+        if is_disabled(owner1) || is_disabled(owner2) {
+            return false;
+        }
+
+        match (owner1, owner2) {
+            (ColliderOwner::Link(a), ColliderOwner::Link(b)) => a != b && !self.is_allowed(a, b),
+            (ColliderOwner::Environment, ColliderOwner::Environment) => false,
+            (ColliderOwner::Link(link), ColliderOwner::Attached(object))
+            | (ColliderOwner::Attached(object), ColliderOwner::Link(link)) => {
+                !self.is_touch_link(object, link)
+            }
+            (ColliderOwner::Attached(a), ColliderOwner::Attached(b)) => a != b,
+            _ => true,
+        }
+    }
+}
+
+impl PhysicsHooks for AllowedCollisionMatrix {
+    fn filter_contact_pair(&self, context: &PairFilterContext) -> Option<SolverFlags> {
+        let owner1 = ColliderOwner::from_user_data(context.colliders[context.collider1].user_data);
+        let owner2 = ColliderOwner::from_user_data(context.colliders[context.collider2].user_data);
+
+        self.can_collide(owner1, owner2)
+            .then_some(SolverFlags::empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ColliderOwner::{Attached, Environment, Link};
+
+    #[test]
+    fn allowed_pairs_are_symmetric() {
+        let mut acm = AllowedCollisionMatrix::default();
+        assert!(acm.can_collide(Link(0), Link(1)));
+        assert!(!acm.can_collide(Link(1), Link(1)));
+
+        acm.allow(1, 0);
+        assert!(acm.is_allowed(0, 1));
+        assert!(!acm.can_collide(Link(0), Link(1)));
+        assert!(!acm.can_collide(Link(1), Link(0)));
+        assert!(acm.can_collide(Link(0), Link(2)));
+
+        acm.disallow(0, 1);
+        assert!(acm.can_collide(Link(0), Link(1)));
+
+        acm.allow(0, 1);
+        acm.clear();
+        assert!(acm.can_collide(Link(0), Link(1)));
+    }
+
+    #[test]
+    fn disabled_links_collide_with_nothing() {
+        let mut acm = AllowedCollisionMatrix::default();
+        acm.set_link_enabled(2, false);
+        acm.set_touch_links(7, [0]);
+
+        assert!(!acm.can_collide(Link(2), Link(0)));
+        assert!(!acm.can_collide(Environment, Link(2)));
+        assert!(!acm.can_collide(Link(2), Attached(7)));
+        assert!(acm.can_collide(Link(0), Link(1)));
+        assert!(acm.can_collide(Link(1), Environment));
+
+        // clearing the allowed pairs keeps the disabled links and touch links
+        acm.clear();
+        assert!(!acm.can_collide(Link(2), Environment));
+        assert!(!acm.can_collide(Link(0), Attached(7)));
+
+        acm.set_link_enabled(2, true);
+        assert!(acm.can_collide(Link(2), Link(0)));
+        assert!(acm.can_collide(Environment, Link(2)));
+    }
+
+    #[test]
+    fn attached_objects_ignore_their_touch_links() {
+        let mut acm = AllowedCollisionMatrix::default();
+        acm.set_touch_links(3, [4, 5]);
+
+        assert!(!acm.can_collide(Attached(3), Link(4)));
+        assert!(!acm.can_collide(Link(5), Attached(3)));
+        assert!(acm.can_collide(Attached(3), Link(6)));
+        assert!(acm.can_collide(Attached(3), Environment));
+        assert!(acm.can_collide(Attached(3), Attached(8)));
+        assert!(!acm.can_collide(Attached(3), Attached(3)));
+        // touch links are per object
+        assert!(acm.can_collide(Attached(8), Link(4)));
+        assert!(!acm.can_collide(Environment, Environment));
+
+        acm.remove_touch_links(3);
+        assert!(acm.can_collide(Attached(3), Link(4)));
+    }
+}
@@ -1,16 +1,46 @@
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use rapier3d::math::{Isometry, Real};
+use rapier3d::parry::bounding_volume::Aabb;
+use rapier3d::parry::partitioning::QbvhDataGenerator;
+use rapier3d::parry::query::{self, ClosestPoints, Unsupported};
 use rapier3d::prelude::{
-    ActiveCollisionTypes, ActiveEvents, BroadPhaseMultiSap, ColliderBuilder, ColliderHandle,
-    ColliderSet, CollisionPipeline, IntegrationParameters, IslandManager, NarrowPhase,
-    QueryPipeline, RigidBodySet,
+    BroadPhaseMultiSap, Collider, ColliderHandle, ColliderSet, CollisionPipeline,
+    IntegrationParameters, IslandManager, NarrowPhase, PhysicsHooks, QueryPipeline, RigidBodySet,
 };
 
+use super::{AllowedCollisionMatrix, ColliderOwner};
+
+/// Bounding boxes of the colliders grown by their contact skin and the prediction
+/// distance, i.e. covering everything a collider can be in contact with.
+struct CollisionAabbs<'a> {
+    colliders: &'a ColliderSet,
+    prediction: Real,
+}
+
+impl<'a> QbvhDataGenerator<ColliderHandle> for CollisionAabbs<'a> {
+    fn size_hint(&self) -> usize {
+        self.colliders.len()
+    }
+
+    fn for_each(&mut self, mut f: impl FnMut(ColliderHandle, Aabb)) {
+        for (handle, collider) in self.colliders.iter_enabled() {
+            f(handle, collider.compute_collision_aabb(self.prediction))
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct SimpleCollisionPipeline {
     pub collider_set: ColliderSet,
 
     pub query_pipeline: QueryPipeline,
 
-    /// we don't use these (but we need them for various calls). Propose simplifying to not need them.
+    /// link pairs that are excluded from collision checking
+    pub allowed_collisions: AllowedCollisionMatrix,
+
+    /// not used for simulation, but required by rapier (e.g. by `ColliderSet::remove`)
     pub rigid_body_set: RigidBodySet,
     pub island_manager: IslandManager, // awkwardly required for ColliderSet::remove
 
@@ -20,10 +50,36 @@ pub struct SimpleCollisionPipeline {
 
     broad_phase: BroadPhaseMultiSap,
     pub narrow_phase: NarrowPhase,
+
+    /// what the query pipeline was last built from with exact bounding boxes, if it still is
+    exact_query_colliders: Option<Vec<ColliderState>>,
+}
+
+/// Handle, pose and shape (by address, as shapes are shared and replaced as a whole) of
+/// a collider.
+type ColliderState = (ColliderHandle, Isometry<Real>, usize);
+
+impl Clone for SimpleCollisionPipeline {
+    fn clone(&self) -> Self {
+        Self {
+            collider_set: self.collider_set.clone(),
+            query_pipeline: self.query_pipeline.clone(),
+            allowed_collisions: self.allowed_collisions.clone(),
+            rigid_body_set: self.rigid_body_set.clone(),
+            island_manager: self.island_manager.clone(),
+            integration_parameters: self.integration_parameters,
+            // the pipeline only holds scratch data for a step
+            collision_pipeline: CollisionPipeline::new(),
+            broad_phase: self.broad_phase.clone(),
+            narrow_phase: self.narrow_phase.clone(),
+            exact_query_colliders: self.exact_query_colliders.clone(),
+        }
+    }
 }
 
 impl SimpleCollisionPipeline {
     pub fn update(&mut self) {
+        self.exact_query_colliders = None;
         self.collision_pipeline.step(
             self.integration_parameters.prediction_distance(),
             // PREDICTION_DISTANCE, // would prefer IntegrationParameters::DEFAULT_PREDICTION_DISTANCE
@@ -32,7 +88,23 @@ impl SimpleCollisionPipeline {
             &mut self.rigid_body_set,
             &mut self.collider_set,
             Some(&mut self.query_pipeline),
+            &self.allowed_collisions,
             &(),
+        );
+    }
+
+    /// Same as [`SimpleCollisionPipeline::update`], with pairs filtered by `hooks` instead
+    /// of the collision matrix (e.g. for colliders of several robots).
+    pub fn update_with_hooks(&mut self, hooks: &dyn PhysicsHooks) {
+        self.exact_query_colliders = None;
+        self.collision_pipeline.step(
+            self.integration_parameters.prediction_distance(),
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.rigid_body_set,
+            &mut self.collider_set,
+            Some(&mut self.query_pipeline),
+            hooks,
             &(),
         );
     }
@@ -44,78 +116,121 @@ impl SimpleCollisionPipeline {
             .any(|pair| pair.has_any_active_contact)
     }
 
-    pub fn print_collision_info(&self) {
-        self.narrow_phase
-            .contact_graph()
-            .interactions()
-            .for_each(|pair| {
-                if let Some(contact) = pair.find_deepest_contact() {
-                    dbg!(contact);
-                    dbg!(pair.collider1, pair.collider2);
-                }
-            });
+    /// Rebuilds the query pipeline from the current collider positions, for
+    /// [`SimpleCollisionPipeline::for_each_colliding_pair`].
+    pub fn update_query_pipeline(&mut self) {
+        self.exact_query_colliders = None;
+        self.query_pipeline.update_with_generator(CollisionAabbs {
+            colliders: &self.collider_set,
+            prediction: self.integration_parameters.prediction_distance(),
+        });
     }
 
-    pub fn get_colliding_pairs(&self) -> Vec<(ColliderHandle, ColliderHandle)> {
-        self.narrow_phase
-            .contact_graph()
-            .interactions()
-            .filter_map(|pair| {
-                dbg!("heee");
-                pair.find_deepest_contact().map(|_contact| {
-                    dbg!((_contact));
-                    (pair.collider1, pair.collider2)
-                })
+    /// Builds the query pipeline from the exact bounding boxes of the colliders, for ray
+    /// casts and point queries. Nothing is done if no collider was added, removed, moved or reshaped since
+    /// the last call (and the pipeline was not rebuilt for anything else in between).
+    pub fn update_exact_query_pipeline(&mut self) {
+        let colliders: Vec<ColliderState> = self
+            .collider_set
+            .iter_enabled()
+            .map(|(handle, collider)| {
+                let shape = collider.shared_shape();
+                (
+                    handle,
+                    *collider.position(),
+                    Arc::as_ptr(&shape.0) as *const () as usize,
+                )
             })
-            .collect()
+            .collect();
+        if self.exact_query_colliders.as_ref() == Some(&colliders) {
+            return;
+        }
+        self.query_pipeline.update(&self.collider_set);
+        self.exact_query_colliders = Some(colliders);
     }
-}
 
-fn aaa() {
-    let mut rigid_body_set = RigidBodySet::new();
-    let mut collider_set = ColliderSet::new();
-
-    /* Create the ground. */
-    let collider_a = ColliderBuilder::cuboid(1.0, 1.0, 1.0)
-        .active_collision_types(ActiveCollisionTypes::all())
-        .sensor(true)
-        .active_events(ActiveEvents::COLLISION_EVENTS)
-        .build();
-
-    let a_handle = collider_set.insert(collider_a);
-
-    let collider_b = ColliderBuilder::cuboid(1.0, 1.0, 1.0)
-        .active_collision_types(ActiveCollisionTypes::all())
-        .sensor(true)
-        .active_events(ActiveEvents::COLLISION_EVENTS)
-        .build();
-
-    let _ = collider_set.insert(collider_b);
-
-    let integration_parameters = IntegrationParameters::default();
-    let mut broad_phase = BroadPhaseMultiSap::new();
-    let mut narrow_phase = NarrowPhase::new();
-    let mut collision_pipeline = CollisionPipeline::new();
-    let physics_hooks = ();
-
-    collision_pipeline.step(
-        integration_parameters.prediction_distance(),
-        &mut broad_phase,
-        &mut narrow_phase,
-        &mut rigid_body_set,
-        &mut collider_set,
-        None,
-        &physics_hooks,
-        &(),
-    );
-
-    let mut hit = false;
-
-    for (_, _, intersecting) in narrow_phase.intersection_pairs_with(a_handle) {
-        if intersecting {
-            hit = true;
+    /// Visits pairs of colliders in contact without running the broad and narrow phases:
+    /// candidates come straight from the query pipeline, no contact manifold is computed,
+    /// and the search stops as soon as `on_contact` breaks. Contact is decided as in
+    /// [`SimpleCollisionPipeline::update`], i.e. the colliders (grown by their contact
+    /// skins) are closer than the prediction distance.
+    ///
+    /// Only pairs allowed by the collision matrix and accepted by `filter` are tested; the
+    /// filter is asked again for every pair, so it can narrow down the search as contacts
+    /// are found. The query pipeline must be up to date, see
+    /// [`SimpleCollisionPipeline::update_query_pipeline`].
+    pub fn for_each_colliding_pair(
+        &self,
+        mut filter: impl FnMut(&Collider, &Collider) -> bool,
+        mut on_contact: impl FnMut(&Collider, &Collider) -> ControlFlow<()>,
+    ) -> Result<(), Unsupported> {
+        let prediction = self.integration_parameters.prediction_distance();
+
+        for (handle1, collider1) in self.collider_set.iter_enabled() {
+            let owner1 = ColliderOwner::from_user_data(collider1.user_data);
+            // obstacles never collide with each other, so they are only ever the candidates
+            if owner1 == ColliderOwner::Environment {
+                continue;
+            }
+
+            let mut result = Ok(ControlFlow::Continue(()));
+            self.query_pipeline.colliders_with_aabb_intersecting_aabb(
+                &collider1.compute_collision_aabb(prediction),
+                |&handle2| {
+                    let Some(collider2) = self.collider_set.get(handle2) else {
+                        return true;
+                    };
+                    let owner2 = ColliderOwner::from_user_data(collider2.user_data);
+                    // test every pair of moving colliders once
+                    if owner2 != ColliderOwner::Environment
+                        && handle1.into_raw_parts() >= handle2.into_raw_parts()
+                    {
+                        return true;
+                    }
+                    if !collider1
+                        .collision_groups()
+                        .test(collider2.collision_groups())
+                        || !self.allowed_collisions.can_collide(owner1, owner2)
+                        || !filter(collider1, collider2)
+                    {
+                        return true;
+                    }
+
+                    let margin = collider1.contact_skin() + collider2.contact_skin() + prediction;
+                    match query::closest_points(
+                        collider1.position(),
+                        collider1.shape(),
+                        collider2.position(),
+                        collider2.shape(),
+                        margin,
+                    ) {
+                        Ok(ClosestPoints::Disjoint) => true,
+                        Ok(_) => {
+                            result = Ok(on_contact(collider1, collider2));
+                            matches!(result, Ok(ControlFlow::Continue(())))
+                        }
+                        Err(err) => {
+                            result = Err(err);
+                            false
+                        }
+                    }
+                },
+            );
+            match result {
+                Ok(ControlFlow::Continue(())) => (),
+                Ok(ControlFlow::Break(())) => return Ok(()),
+                Err(err) => return Err(err),
+            }
         }
+        Ok(())
     }
 
-    assert!(hit, "No hit found");
+    /// Iterates over the pairs of colliders that are currently in contact.
+    pub fn colliding_pairs(&self) -> impl Iterator<Item = (ColliderHandle, ColliderHandle)> + '_ {
+        self.narrow_phase
+            .contact_graph()
+            .interactions()
+            .filter(|pair| pair.has_any_active_contact)
+            .map(|pair| (pair.collider1, pair.collider2))
+    }
 }
@@ -1,86 +1,99 @@
+mod acm;
 mod checker;
 
+pub use acm::AllowedCollisionMatrix;
 pub use checker::SimpleCollisionPipeline;
 use rapier3d::prelude::{
-    ActiveCollisionTypes, ActiveEvents, ColliderBuilder, Group, InteractionGroups,
+    ActiveCollisionTypes, ActiveEvents, ActiveHooks, ColliderBuilder, Group, InteractionGroups,
 };
 
+/// Collision group of every robot link.
+pub const ROBOT_LINK_GROUP: Group = Group::GROUP_1;
+/// Collision group of environment obstacles. Every link collides with it,
+/// but obstacles never collide with each other.
+pub const ENVIRONMENT_GROUP: Group = Group::GROUP_2;
+
+/// What a collider belongs to. This is stored in the collider's `user_data`, so that
+/// the pair filter can tell links and obstacles apart without any lookup table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColliderOwner {
+    /// A link of the robot, by its index in the urdf.
+    Link(usize),
+    /// A static environment obstacle.
+    Environment,
+    /// An object attached to a link of the robot, by its id.
+    Attached(usize),
+}
+
+const OWNER_KIND_SHIFT: u32 = 64;
+const OWNER_KIND_MASK: u128 = u32::MAX as u128;
+/// colliders of a world shared by several robots also hold the id of their robot
+const OWNER_ROBOT_SHIFT: u32 = 96;
+const OWNER_KIND_LINK: u128 = 0;
+const OWNER_KIND_ENVIRONMENT: u128 = 1;
+const OWNER_KIND_ATTACHED: u128 = 2;
+
+impl From<ColliderOwner> for u128 {
+    fn from(owner: ColliderOwner) -> Self {
+        match owner {
+            ColliderOwner::Link(idx) => (OWNER_KIND_LINK << OWNER_KIND_SHIFT) | idx as u128,
+            ColliderOwner::Environment => OWNER_KIND_ENVIRONMENT << OWNER_KIND_SHIFT,
+            ColliderOwner::Attached(id) => (OWNER_KIND_ATTACHED << OWNER_KIND_SHIFT) | id as u128,
+        }
+    }
+}
+
+impl ColliderOwner {
+    pub fn from_user_data(user_data: u128) -> Self {
+        let idx = (user_data & u64::MAX as u128) as usize;
+        match (user_data >> OWNER_KIND_SHIFT) & OWNER_KIND_MASK {
+            OWNER_KIND_LINK => ColliderOwner::Link(idx),
+            OWNER_KIND_ATTACHED => ColliderOwner::Attached(idx),
+            _ => ColliderOwner::Environment,
+        }
+    }
+
+    /// User data of a collider of the given robot, in a world shared by several robots.
+    pub fn to_user_data_of_robot(self, robot: usize) -> u128 {
+        u128::from(self) | ((robot as u128) << OWNER_ROBOT_SHIFT)
+    }
+
+    /// The robot of a collider, in a world shared by several robots.
+    pub fn robot_from_user_data(user_data: u128) -> usize {
+        (user_data >> OWNER_ROBOT_SHIFT) as usize
+    }
+}
+
 pub trait ColliderBuilderActivateRobotLinkCollision {
     fn activate_as_robot_link(self, link_idx: usize) -> Self;
-    fn activate_as_robot_link_with_exclude_group(
-        self,
-        link_idx: usize,
-        exclude_group: Group,
-    ) -> Self;
+    fn activate_as_environment_obstacle(self) -> Self;
+    fn activate_as_attached_object(self, object_id: usize) -> Self;
 }
 
 impl ColliderBuilderActivateRobotLinkCollision for ColliderBuilder {
     fn activate_as_robot_link(self, link_idx: usize) -> Self {
         self.active_collision_types(ActiveCollisionTypes::all())
             .active_events(ActiveEvents::all())
-            .collision_groups(collision_group(link_idx, None))
+            // link-link pairs are filtered by the allowed collision matrix
+            .active_hooks(ActiveHooks::FILTER_CONTACT_PAIRS)
+            .user_data(ColliderOwner::Link(link_idx).into())
+            .collision_groups(InteractionGroups::new(ROBOT_LINK_GROUP, Group::ALL))
     }
-    fn activate_as_robot_link_with_exclude_group(
-        self,
-        link_idx: usize,
-        exclude_group: Group,
-    ) -> Self {
+    fn activate_as_environment_obstacle(self) -> Self {
         self.active_collision_types(ActiveCollisionTypes::all())
             .active_events(ActiveEvents::all())
-            .collision_groups(collision_group(link_idx, Some(exclude_group)))
-    }
-}
-
-pub fn group_flag_from_idx(link_idx: usize) -> Group {
-    match link_idx {
-        0 => Group::GROUP_1,
-        1 => Group::GROUP_2,
-        2 => Group::GROUP_3,
-        3 => Group::GROUP_4,
-        4 => Group::GROUP_5,
-        5 => Group::GROUP_6,
-        6 => Group::GROUP_7,
-        7 => Group::GROUP_8,
-        8 => Group::GROUP_9,
-        9 => Group::GROUP_10,
-        10 => Group::GROUP_11,
-        11 => Group::GROUP_12,
-        12 => Group::GROUP_13,
-        13 => Group::GROUP_14,
-        14 => Group::GROUP_15,
-        15 => Group::GROUP_16,
-        16 => Group::GROUP_17,
-        17 => Group::GROUP_18,
-        18 => Group::GROUP_19,
-        19 => Group::GROUP_20,
-        20 => Group::GROUP_21,
-        21 => Group::GROUP_22,
-        22 => Group::GROUP_23,
-        23 => Group::GROUP_24,
-        24 => Group::GROUP_25,
-        25 => Group::GROUP_26,
-        26 => Group::GROUP_27,
-        27 => Group::GROUP_28,
-        28 => Group::GROUP_29,
-        29 => Group::GROUP_30,
-        30 => Group::GROUP_31,
-        31 => Group::GROUP_32,
-        _ => panic!("Link index only supports up-to 32 links"),
+            .user_data(ColliderOwner::Environment.into())
+            .collision_groups(InteractionGroups::new(
+                ENVIRONMENT_GROUP,
+                !ENVIRONMENT_GROUP,
+            ))
     }
-}
-
-fn collision_group(link_idx: usize, exclude_group: Option<Group>) -> InteractionGroups {
-    let mut group = InteractionGroups::default();
-
-    let link_group = group_flag_from_idx(link_idx);
-    // set this to be the link's group
-    group.memberships = link_group;
-    // do not include this link's group in the filter
-    group.filter &= !link_group;
-
-    if let Some(exclude_group) = exclude_group {
-        group.filter &= !exclude_group;
+    fn activate_as_attached_object(self, object_id: usize) -> Self {
+        // attached objects move with the robot, so they are checked like links
+        self.active_collision_types(ActiveCollisionTypes::all())
+            .active_events(ActiveEvents::all())
+            .active_hooks(ActiveHooks::FILTER_CONTACT_PAIRS)
+            .user_data(ColliderOwner::Attached(object_id).into())
+            .collision_groups(InteractionGroups::new(ROBOT_LINK_GROUP, Group::ALL))
     }
-
-    group
 }
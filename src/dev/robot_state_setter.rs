@@ -6,8 +6,11 @@ use bevy_egui::egui::{self, CollapsingHeader, Slider};
 // use bevy_xpbd_3d::prelude::PhysicsGizmos;
 use rand::rngs::SmallRng;
 use rand::{Rng, RngCore, SeedableRng};
+use rapier3d::math::{Isometry, Translation};
+use rapier3d::na::UnitQuaternion;
 use serde::{Deserialize, Serialize};
 
+use crate::robot::plugin::{RobotToCollisionChecker, SetLinkCollisionEnabled, SolveIk};
 use crate::robot_vis::{visuals::UrdfLoadRequest, RobotLinkMeshes, RobotState};
 
 pub(super) fn plugin(app: &mut App) {
@@ -19,6 +22,17 @@ pub(super) fn plugin(app: &mut App) {
 
 pub(crate) struct EditorState {
     rng: SmallRng,
+    ik: IkRequest,
+}
+
+/// The inverse kinematics target being edited.
+#[derive(Default)]
+struct IkRequest {
+    link: String,
+    xyz: [f64; 3],
+    rpy: [f64; 3],
+    position_only: bool,
+    reject_colliding: bool,
 }
 
 impl EditorState {
@@ -31,6 +45,7 @@ impl Default for EditorState {
     fn default() -> Self {
         Self {
             rng: SmallRng::seed_from_u64(42),
+            ik: IkRequest::default(),
         }
     }
 }
@@ -48,12 +63,18 @@ impl EditorWindow for RobotStateEditorWindow {
         ui: &mut egui::Ui,
     ) {
         if ui.button("load robot").clicked() {
-            world.send_event(UrdfLoadRequest(
-                "/home/soraxas/git-repos/robot-simulator-rs/assets/panda/urdf/panda_relative.urdf"
-                    .to_owned(),
+            world.send_event(UrdfLoadRequest::new(
+                "/home/soraxas/git-repos/robot-simulator-rs/assets/panda/urdf/panda_relative.urdf",
             ));
         }
 
+        let mut link_collisions = world
+            .get_resource::<RobotToCollisionChecker>()
+            .map(RobotToCollisionChecker::link_collisions_enabled)
+            .unwrap_or_default();
+        let mut link_collision_toggles = Vec::new();
+        let mut ik_requests = Vec::new();
+
         for (mut state, entity) in world.query::<(&mut RobotState, Entity)>().iter_mut(world) {
             let mut changed = false;
             {
@@ -115,6 +136,92 @@ impl EditorWindow for RobotStateEditorWindow {
                                     .expect("Front-end should prevent any out-of-range error");
                             }
                         }
+
+                        CollapsingHeader::new("Base pose")
+                            .id_source((entity, "base pose"))
+                            .show(ui, |ui| {
+                                let t = state.base_pose.translation.vector;
+                                let (roll, pitch, yaw) = state.base_pose.rotation.euler_angles();
+                                let mut xyz = [t.x, t.y, t.z];
+                                let mut rpy = [roll, pitch, yaw];
+                                let mut base_changed = false;
+                                for (label, values) in [("xyz", &mut xyz), ("rpy", &mut rpy)] {
+                                    ui.horizontal(|ui| {
+                                        ui.label(label);
+                                        for value in values.iter_mut() {
+                                            base_changed |= ui
+                                                .add(egui::DragValue::new(value).speed(0.01))
+                                                .changed();
+                                        }
+                                    });
+                                }
+                                if base_changed {
+                                    state.base_pose = Isometry::from_parts(
+                                        Translation::new(xyz[0], xyz[1], xyz[2]),
+                                        UnitQuaternion::from_euler_angles(rpy[0], rpy[1], rpy[2]),
+                                    );
+                                    changed = true;
+                                }
+                            });
+
+                        CollapsingHeader::new("Inverse kinematics")
+                            .id_source((entity, "inverse kinematics"))
+                            .show(ui, |ui| {
+                                let Some(editor_state) = cx.state_mut::<Self>() else {
+                                    return;
+                                };
+                                let ik = &mut editor_state.ik;
+                                egui::ComboBox::from_id_source((entity, "ik link"))
+                                    .selected_text(ik.link.as_str())
+                                    .show_ui(ui, |ui| {
+                                        for link in &state.urdf_robot.links {
+                                            ui.selectable_value(
+                                                &mut ik.link,
+                                                link.name.clone(),
+                                                link.name.as_str(),
+                                            );
+                                        }
+                                    });
+                                for (label, values) in [("xyz", &mut ik.xyz), ("rpy", &mut ik.rpy)]
+                                {
+                                    ui.horizontal(|ui| {
+                                        ui.label(label);
+                                        for value in values.iter_mut() {
+                                            ui.add(egui::DragValue::new(value).speed(0.01));
+                                        }
+                                    });
+                                }
+                                ui.checkbox(&mut ik.position_only, "Position only");
+                                ui.checkbox(&mut ik.reject_colliding, "Collision free");
+                                if ui.button("Solve").clicked() && !ik.link.is_empty() {
+                                    ik_requests.push(SolveIk {
+                                        robot: entity,
+                                        link: ik.link.clone(),
+                                        target: urdf_rs::Pose {
+                                            xyz: urdf_rs::Vec3(ik.xyz),
+                                            rpy: urdf_rs::Vec3(ik.rpy),
+                                        },
+                                        position_only: ik.position_only,
+                                        reject_colliding: ik.reject_colliding,
+                                    });
+                                }
+                            });
+
+                        if let Some(links) = link_collisions.get_mut(&entity) {
+                            CollapsingHeader::new("Link collisions")
+                                .id_source((entity, "link collisions"))
+                                .show(ui, |ui| {
+                                    for (link, enabled) in links.iter_mut() {
+                                        if ui.checkbox(enabled, link.as_str()).changed() {
+                                            link_collision_toggles.push(SetLinkCollisionEnabled {
+                                                robot: entity,
+                                                link: link.clone(),
+                                                enabled: *enabled,
+                                            });
+                                        }
+                                    }
+                                });
+                        }
                     });
             }
             if changed {
@@ -122,6 +229,13 @@ impl EditorWindow for RobotStateEditorWindow {
             }
         }
 
+        for toggle in link_collision_toggles {
+            world.send_event(toggle);
+        }
+        for request in ik_requests {
+            world.send_event(request);
+        }
+
         ui.separator();
         if let Some(mut collider_mesh_conf) = world.get_resource_mut::<RobotShowColliderMesh>() {
             ui.checkbox(&mut collider_mesh_conf.enabled, "Show collision meshes");
@@ -0,0 +1,253 @@
+use eyre::{ensure, OptionExt, Result};
+use rapier3d::math::{Isometry, Real};
+use rapier3d::prelude::ColliderHandle;
+use urdf_rs::{Geometry, Pose};
+
+use super::{geometry_to_colliders, MeshColliderMode, Robot, RobotError};
+use crate::collision_checker::ColliderBuilderActivateRobotLinkCollision;
+
+/// An object held by the robot (e.g. a part in the gripper), which moves with the link
+/// it is attached to.
+#[derive(Debug, Clone)]
+pub struct AttachedObject {
+    /// Unique id of the object within the robot, used to identify its colliders.
+    pub id: usize,
+    pub link: String,
+    /// Pose of the object relative to the link.
+    pub pose: Isometry<Real>,
+    pub geometry: Geometry,
+    /// Links (in addition to `link`) that the object may touch without being in collision.
+    pub touch_links: Vec<String>,
+    /// Colliders of this object, together with their pose relative to the object's pose.
+    pub colliders: Vec<(ColliderHandle, Isometry<Real>)>,
+}
+
+impl AttachedObject {
+    pub fn contains(&self, handle: ColliderHandle) -> bool {
+        self.colliders.iter().any(|(h, _)| *h == handle)
+    }
+}
+
+impl Robot {
+    /// Attaches an object to a link, replacing any attached object with the same name.
+    /// The object is checked against the world and against every link of the robot,
+    /// except for `link` itself and the `touch_links` (e.g. the fingers holding it).
+    ///
+    /// Relative mesh filenames are resolved against the current working directory.
+    pub fn attach_object(
+        &mut self,
+        name: &str,
+        link: &str,
+        geometry: &Geometry,
+        pose: Isometry<Real>,
+        touch_links: &[&str],
+    ) -> Result<()> {
+        let link_idx = self.link_index(link)?;
+        let touch_link_indices = touch_links
+            .iter()
+            .map(|touch_link| self.link_index(touch_link))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut builders = geometry_to_colliders(
+            &None,
+            geometry,
+            &Pose::default(),
+            MeshColliderMode::default(),
+        );
+        // e.g. a mesh that failed to load, which would otherwise be silently ignored
+        ensure!(
+            !builders.is_empty(),
+            "Attached object '{}' has no collision shape (failed to load its mesh?)",
+            name
+        );
+
+        if self.attached_objects.contains_key(name) {
+            self.detach_object(name)?;
+        }
+
+        let id = self.next_attached_object_id;
+        self.next_attached_object_id += 1;
+
+        let colliders = builders
+            .drain(..)
+            .map(|builder| {
+                let local_pose = builder.position;
+                let collider = builder.activate_as_attached_object(id).build();
+                (
+                    self.collision_checker.collider_set.insert(collider),
+                    local_pose,
+                )
+            })
+            .collect();
+
+        self.collision_checker
+            .allowed_collisions
+            .set_touch_links(id, std::iter::once(link_idx).chain(touch_link_indices));
+
+        self.attached_objects.insert(
+            name.to_owned(),
+            AttachedObject {
+                id,
+                link: link.to_owned(),
+                pose,
+                geometry: geometry.clone(),
+                touch_links: touch_links.iter().map(|l| l.to_string()).collect(),
+                colliders,
+            },
+        );
+        // place the colliders with the link
+        self.update_link_colliders()
+    }
+
+    /// Detaches an object (and removes all of its colliders).
+    pub fn detach_object(&mut self, name: &str) -> Result<AttachedObject> {
+        let object = self
+            .attached_objects
+            .remove(name)
+            .ok_or_else(|| RobotError::UnknownAttachedObject(name.to_owned()))?;
+
+        let checker = &mut self.collision_checker;
+        checker.allowed_collisions.remove_touch_links(object.id);
+        for (handle, _) in &object.colliders {
+            checker.collider_set.remove(
+                *handle,
+                &mut checker.island_manager,
+                &mut checker.rigid_body_set,
+                false,
+            );
+        }
+        Ok(object)
+    }
+
+    /// Name of the attached object that the collider belongs to.
+    pub fn attached_object_of(&self, handle: ColliderHandle) -> Option<&str> {
+        self.attached_objects
+            .iter()
+            .find(|(_, object)| object.contains(handle))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Moves the colliders of the objects attached to a link, given the link's pose.
+    pub(crate) fn update_attached_colliders(
+        &mut self,
+        link: &str,
+        link_pose: &Isometry<Real>,
+    ) -> Result<()> {
+        for object in self.attached_objects.values() {
+            if object.link != link {
+                continue;
+            }
+            for (handle, local_pose) in &object.colliders {
+                self.collision_checker
+                    .collider_set
+                    .get_mut(*handle)
+                    .ok_or_eyre("cannot find collider")?
+                    .set_position(link_pose * object.pose * local_pose);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use rapier3d::math::Vector;
+
+    use super::*;
+    use crate::robot::test_util::{arm, at, ball, slider_position};
+    use crate::robot::CollisionResult;
+
+    #[test]
+    fn object_follows_its_link() {
+        let mut robot = arm();
+        robot
+            .attach_object(
+                "part",
+                "slider",
+                &ball(0.1),
+                Isometry::identity(),
+                &["link2"],
+            )
+            .unwrap();
+        let handle = robot.attached_objects["part"].colliders[0].0;
+        assert_eq!(robot.attached_object_of(handle), Some("part"));
+
+        for q in [[0.0, 0.0, 0.0], [FRAC_PI_2, 0.0, 0.5]] {
+            robot.set_joints(&q).unwrap();
+            robot.update_link_colliders().unwrap();
+            let position = robot.collision_checker.collider_set[handle].translation();
+            assert!((position - Vector::from(slider_position(&q))).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn object_is_not_checked_against_its_link_or_touch_links() {
+        let mut robot = arm();
+        // overlaps the slider and the end of link2
+        robot
+            .attach_object(
+                "part",
+                "slider",
+                &ball(0.1),
+                Isometry::identity(),
+                &["link2"],
+            )
+            .unwrap();
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+
+        // attaching again under the same name replaces the object
+        robot
+            .attach_object("part", "slider", &ball(0.1), Isometry::identity(), &[])
+            .unwrap();
+        assert_eq!(robot.attached_objects.len(), 1);
+        assert_eq!(
+            robot.has_collision().unwrap(),
+            CollisionResult::SelfCollision
+        );
+
+        assert!(robot
+            .attach_object("other", "missing", &ball(0.1), Isometry::identity(), &[])
+            .is_err());
+    }
+
+    #[test]
+    fn object_collides_with_obstacles_until_detached() {
+        let mut robot = arm();
+        // 0.04 away from the slider, but within reach of the attached object
+        robot
+            .add_obstacle("obstacle", &ball(0.05), at([0.54, 0.0, 0.6]))
+            .unwrap();
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+
+        robot
+            .attach_object(
+                "part",
+                "slider",
+                &ball(0.1),
+                Isometry::identity(),
+                &["link2"],
+            )
+            .unwrap();
+        assert_eq!(
+            robot.has_collision().unwrap(),
+            CollisionResult::EnvironmentCollision
+        );
+
+        let object = robot.detach_object("part").unwrap();
+        assert!(object.colliders.iter().all(|(handle, _)| robot
+            .collision_checker
+            .collider_set
+            .get(*handle)
+            .is_none()));
+        assert!(robot.attached_objects.is_empty());
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+
+        let err = robot.detach_object("part").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RobotError>(),
+            Some(RobotError::UnknownAttachedObject(_))
+        ));
+    }
+}
@@ -0,0 +1,263 @@
+//! Virtual joints between the world and the robot's root link, for mobile manipulators
+//! whose base pose is part of the joint vector.
+
+use std::str::FromStr;
+
+use eyre::{ensure, ContextCompat, Result};
+
+/// Degrees of freedom of the robot's root link relative to its base pose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BaseJoint {
+    /// The root link is fixed at the base pose.
+    #[default]
+    Fixed,
+    /// Moves in the xy plane of the base pose and rotates around its z axis: (x, y, theta).
+    Planar,
+    /// Moves freely: (x, y, z, yaw, pitch, roll), the rotations being applied in that order
+    /// around the rotated axes (i.e. the urdf's rpy convention).
+    Floating,
+}
+
+impl BaseJoint {
+    /// Names of the virtual joints, which come first in the joint vector.
+    pub fn joint_names(&self) -> &'static [&'static str] {
+        match self {
+            BaseJoint::Fixed => &[],
+            BaseJoint::Planar => &["virtual_base_x", "virtual_base_y", "virtual_base_theta"],
+            BaseJoint::Floating => &[
+                "virtual_base_x",
+                "virtual_base_y",
+                "virtual_base_z",
+                "virtual_base_yaw",
+                "virtual_base_pitch",
+                "virtual_base_roll",
+            ],
+        }
+    }
+
+    pub fn dof(&self) -> usize {
+        self.joint_names().len()
+    }
+
+    /// Axis of each virtual joint, and whether it is prismatic (or else revolute).
+    fn axes(&self) -> &'static [([f64; 3], bool)] {
+        match self {
+            BaseJoint::Fixed => &[],
+            BaseJoint::Planar => &[
+                ([1., 0., 0.], true),
+                ([0., 1., 0.], true),
+                ([0., 0., 1.], false),
+            ],
+            BaseJoint::Floating => &[
+                ([1., 0., 0.], true),
+                ([0., 1., 0.], true),
+                ([0., 0., 1.], true),
+                ([0., 0., 1.], false),
+                ([0., 1., 0.], false),
+                ([1., 0., 0.], false),
+            ],
+        }
+    }
+}
+
+impl FromStr for BaseJoint {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fixed" => Ok(BaseJoint::Fixed),
+            "planar" => Ok(BaseJoint::Planar),
+            "floating" => Ok(BaseJoint::Floating),
+            _ => Err(eyre::eyre!(
+                "Unknown base joint '{}' (expected one of 'fixed', 'planar' or 'floating')",
+                s
+            )),
+        }
+    }
+}
+
+/// Name of the link the virtual joints start from, which becomes the root of the urdf.
+pub const VIRTUAL_BASE_LINK: &str = "virtual_base";
+
+/// Inserts the virtual joints of `base_joint` (and massless links without geometry between
+/// them) above the root link. The new links are appended, so that the indices of the
+/// existing links are unchanged.
+pub fn add_virtual_base_joints(
+    urdf_robot: &mut urdf_rs::Robot,
+    base_joint: BaseJoint,
+) -> Result<()> {
+    if base_joint == BaseJoint::Fixed {
+        return Ok(());
+    }
+
+    let root_link = urdf_robot
+        .links
+        .iter()
+        .find(|link| {
+            !urdf_robot
+                .joints
+                .iter()
+                .any(|joint| joint.child.link == link.name)
+        })
+        .wrap_err("The urdf has no root link")?
+        .name
+        .clone();
+    ensure!(
+        !urdf_robot
+            .links
+            .iter()
+            .any(|link| link.name.starts_with(VIRTUAL_BASE_LINK)),
+        "The urdf already has virtual base links"
+    );
+
+    let mut parent = VIRTUAL_BASE_LINK.to_owned();
+    urdf_robot.links.push(virtual_link(&parent));
+    let num_joints = base_joint.dof();
+    for (i, (name, (axis, prismatic))) in base_joint
+        .joint_names()
+        .iter()
+        .zip(base_joint.axes())
+        .enumerate()
+    {
+        let child = if i + 1 == num_joints {
+            root_link.clone()
+        } else {
+            let child = format!("{}_link", name);
+            urdf_robot.links.push(virtual_link(&child));
+            child
+        };
+        urdf_robot.joints.push(urdf_rs::Joint {
+            name: (*name).to_owned(),
+            joint_type: if *prismatic {
+                urdf_rs::JointType::Prismatic
+            } else {
+                urdf_rs::JointType::Continuous
+            },
+            origin: urdf_rs::Pose::default(),
+            parent: urdf_rs::LinkName {
+                link: parent.clone(),
+            },
+            child: urdf_rs::LinkName {
+                link: child.clone(),
+            },
+            axis: urdf_rs::Axis {
+                xyz: urdf_rs::Vec3(*axis),
+            },
+            // equal bounds mean no limits for k
+            limit: urdf_rs::JointLimit {
+                lower: 0.0,
+                upper: 0.0,
+                effort: 0.0,
+                velocity: 0.0,
+            },
+            calibration: None,
+            dynamics: None,
+            mimic: None,
+            safety_controller: None,
+        });
+        parent = child;
+    }
+    Ok(())
+}
+
+fn virtual_link(name: &str) -> urdf_rs::Link {
+    urdf_rs::Link {
+        name: name.to_owned(),
+        inertial: urdf_rs::Inertial::default(),
+        visual: Vec::new(),
+        collision: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::{Robot, UrdfRobotOption};
+
+    const URDF: &str = r#"<robot name="arm">
+  <link name="base" />
+  <link name="link1" />
+  <joint name="joint1" type="revolute">
+    <parent link="base" />
+    <child link="link1" />
+    <axis xyz="0 0 1" />
+    <limit lower="-1" upper="1" effort="0" velocity="0" />
+  </joint>
+</robot>"#;
+
+    fn robot(base_joint: BaseJoint) -> Robot {
+        let option = UrdfRobotOption {
+            base_joint,
+            ..Default::default()
+        };
+        Robot::from_urdf_robot_with_option(urdf_rs::read_from_string(URDF).unwrap(), None, &option)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_base_joints() {
+        assert_eq!("fixed".parse::<BaseJoint>().unwrap(), BaseJoint::Fixed);
+        assert_eq!("planar".parse::<BaseJoint>().unwrap(), BaseJoint::Planar);
+        assert_eq!(
+            "floating".parse::<BaseJoint>().unwrap(),
+            BaseJoint::Floating
+        );
+        assert!("revolute".parse::<BaseJoint>().is_err());
+    }
+
+    #[test]
+    fn virtual_joints_come_first_without_limits() {
+        for (base_joint, dof) in [
+            (BaseJoint::Fixed, 0),
+            (BaseJoint::Planar, 3),
+            (BaseJoint::Floating, 6),
+        ] {
+            assert_eq!(base_joint.dof(), dof);
+
+            let mut robot = robot(base_joint);
+            assert_eq!(robot.dof(), dof + 1, "{:?}", base_joint);
+            let names = robot.active_joint_names();
+            assert_eq!(&names[..dof], base_joint.joint_names());
+            assert_eq!(names[dof], "joint1");
+
+            let limits: Vec<_> = robot
+                .robot_chain
+                .iter_joints()
+                .map(|joint| joint.limits)
+                .collect();
+            assert!(
+                limits[..dof].iter().all(Option::is_none),
+                "{:?}",
+                base_joint
+            );
+            assert!(limits[dof].is_some());
+
+            // far away, and turned more than once
+            let mut joints = vec![10.0; dof];
+            joints.push(0.5);
+            robot.set_joints(&joints).unwrap();
+            joints[dof] = 2.0;
+            assert!(robot.set_joints(&joints).is_err());
+        }
+    }
+
+    #[test]
+    fn virtual_links_are_appended_above_the_root() {
+        let mut urdf = urdf_rs::read_from_string(URDF).unwrap();
+        add_virtual_base_joints(&mut urdf, BaseJoint::Planar).unwrap();
+
+        // existing links keep their indices
+        assert_eq!(urdf.links[0].name, "base");
+        assert_eq!(urdf.links[1].name, "link1");
+        assert_eq!(urdf.links[2].name, VIRTUAL_BASE_LINK);
+        let last = urdf
+            .joints
+            .iter()
+            .find(|joint| joint.name == "virtual_base_theta")
+            .unwrap();
+        assert_eq!(last.child.link, "base");
+        assert_eq!(last.joint_type, urdf_rs::JointType::Continuous);
+
+        assert!(add_virtual_base_joints(&mut urdf, BaseJoint::Planar).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use eyre::{ensure, Result};
+use rayon::prelude::*;
+
+use super::{CollisionResult, Robot, RobotError};
+
+impl Robot {
+    /// Sets the joints and checks for collision. Joint limit violations are reported as
+    /// [`CollisionResult::JointLimitViolation`] rather than as an error.
+    pub fn collision_at(&mut self, joints: &[f32]) -> Result<CollisionResult> {
+        match self.set_joints_for_check(joints)? {
+            Some(result) => Ok(result),
+            None => self.has_collision(),
+        }
+    }
+
+    /// Like [`Robot::collision_at`], but with [`Robot::has_collision_fast`].
+    pub fn collision_at_fast(&mut self, joints: &[f32]) -> Result<CollisionResult> {
+        match self.set_joints_for_check(joints)? {
+            Some(result) => Ok(result),
+            None => self.has_collision_fast(),
+        }
+    }
+
+    /// Sets the joints, returning the result of the check if the joints are out of limits.
+    fn set_joints_for_check(&mut self, joints: &[f32]) -> Result<Option<CollisionResult>> {
+        match self.set_joints(joints) {
+            Ok(()) => Ok(None),
+            Err(err) => match err.downcast::<RobotError>() {
+                Ok(RobotError::SetJointLimitViolation) => {
+                    Ok(Some(CollisionResult::JointLimitViolation))
+                }
+                Ok(e) => Err(e.into()),
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    /// Checks many configurations in parallel. `configs` holds the configurations
+    /// back-to-back, `dof` values each. Every worker thread checks its share of the
+    /// configurations on its own clone of the robot, and the results are returned in
+    /// input order. The joint positions of `self` are left unchanged.
+    ///
+    /// Only the outcome of each check is needed, so this uses [`Robot::has_collision_fast`].
+    pub fn has_collision_batch(&self, configs: &[f32], dof: usize) -> Result<Vec<CollisionResult>> {
+        ensure!(
+            dof > 0 && configs.len() % dof == 0,
+            "Configurations of size {} cannot be made of {} values",
+            dof,
+            configs.len()
+        );
+
+        let num_configs = configs.len() / dof;
+        // a few chunks per thread, so that threads finishing early can pick up more work
+        let chunk_size = (num_configs / (rayon::current_num_threads() * 4)).max(1);
+
+        let results = configs
+            .par_chunks(chunk_size * dof)
+            .map_init(
+                || self.clone(),
+                |robot, chunk| {
+                    chunk
+                        .chunks_exact(dof)
+                        .map(|joints| robot.collision_at_fast(joints))
+                        .collect::<Result<Vec<_>>>()
+                },
+            )
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(results.into_iter().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::robot::test_util::{arm, at, ball, slider_position, SELF_COLLIDING};
+
+    #[test]
+    fn batch_matches_single_checks() {
+        let mut robot = arm();
+        let turned = [FRAC_PI_2, 0.0, 0.0];
+        robot
+            .add_obstacle("obstacle", &ball(0.05), at(slider_position(&turned)))
+            .unwrap();
+        robot.set_joints(&[0.1, 0.2, 0.3]).unwrap();
+
+        let configs: Vec<f32> = [
+            [0.0; 3],
+            SELF_COLLIDING,
+            turned,
+            [0.0, 2.0, 0.0],
+            [0.0, 0.0, 0.5],
+        ]
+        .iter()
+        .cycle()
+        .take(50)
+        .flatten()
+        .copied()
+        .collect();
+        let results = robot.has_collision_batch(&configs, 3).unwrap();
+        assert_eq!(results.len(), 50);
+        assert_eq!(robot.robot_chain.joint_positions(), [0.1, 0.2, 0.3]);
+
+        assert_eq!(
+            results[..5],
+            [
+                CollisionResult::Free,
+                CollisionResult::SelfCollision,
+                CollisionResult::EnvironmentCollision,
+                CollisionResult::JointLimitViolation,
+                CollisionResult::Free,
+            ]
+        );
+        for (joints, result) in configs.chunks(3).zip(&results) {
+            assert_eq!(robot.collision_at(joints).unwrap(), *result);
+            assert_eq!(robot.collision_at_fast(joints).unwrap(), *result);
+        }
+    }
+
+    #[test]
+    fn batch_rejects_malformed_configurations() {
+        let robot = arm();
+        assert!(robot.has_collision_batch(&[0.0; 4], 3).is_err());
+        assert!(robot.has_collision_batch(&[0.0; 3], 0).is_err());
+        // rows of the wrong size for the robot
+        assert!(robot.has_collision_batch(&[0.0; 4], 2).is_err());
+        assert!(robot.has_collision_batch(&[], 3).unwrap().is_empty());
+    }
+}
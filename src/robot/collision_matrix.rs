@@ -0,0 +1,237 @@
+//! Automatic generation of the disabled collision pairs, in the same spirit as the
+//! MoveIt setup assistant: link pairs that are adjacent, in collision at the default
+//! pose, always in collision, or never in collision are excluded from checking.
+
+use std::collections::HashMap;
+
+use eyre::{ensure, Result};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use super::srdf::DisableCollisions;
+use super::Robot;
+
+/// Why a link pair was disabled. The names follow the reasons used by MoveIt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DisabledReason {
+    /// The links are connected by a joint.
+    Adjacent,
+    /// The links are in collision at the default configuration.
+    Default,
+    /// The links were in collision in (almost) every sampled configuration.
+    Always,
+    /// The links were never in collision in any sampled configuration.
+    Never,
+}
+
+impl DisabledReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisabledReason::Adjacent => "Adjacent",
+            DisabledReason::Default => "Default",
+            DisabledReason::Always => "Always",
+            DisabledReason::Never => "Never",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionMatrixGenerationOption {
+    /// Number of random joint configurations to sample.
+    pub num_samples: usize,
+    /// A pair is considered always in collision if it collides in at least this
+    /// fraction of the samples.
+    pub min_collision_fraction: f32,
+    pub seed: u64,
+}
+
+impl Default for CollisionMatrixGenerationOption {
+    fn default() -> Self {
+        Self {
+            num_samples: 10_000,
+            min_collision_fraction: 0.95,
+            seed: 42,
+        }
+    }
+}
+
+impl Robot {
+    /// Samples random joint configurations (within the joint limits) to find link pairs
+    /// that never need to be checked. The collision matrix and the joint positions of the
+    /// robot are left unchanged; use [`Robot::apply_disabled_collisions`] to apply the
+    /// result, or [`super::srdf::disable_collisions_to_srdf`] to export it.
+    pub fn generate_disabled_collisions(
+        &mut self,
+        option: &CollisionMatrixGenerationOption,
+    ) -> Result<Vec<DisableCollisions>> {
+        ensure!(
+            option.num_samples > 0,
+            "At least one sample is needed to generate the disabled collisions"
+        );
+
+        let original_matrix = std::mem::take(&mut self.collision_checker.allowed_collisions);
+        let original_joints = self.robot_chain.joint_positions();
+
+        let result = self.sample_disabled_collisions(option);
+
+        self.collision_checker.allowed_collisions = original_matrix;
+        // as-is, even if the original positions are out of the joint limits
+        self.robot_chain
+            .set_joint_positions_unchecked(&original_joints);
+
+        let reasons = result?;
+        let mut pairs: Vec<_> = reasons.into_iter().collect();
+        pairs.sort_by_key(|((a, b), _)| (*a, *b));
+
+        Ok(pairs
+            .into_iter()
+            .map(|((a, b), reason)| DisableCollisions {
+                link1: self.urdf_robot.links[a].name.clone(),
+                link2: self.urdf_robot.links[b].name.clone(),
+                reason: Some(reason.as_str().to_owned()),
+            })
+            .collect())
+    }
+
+    fn sample_disabled_collisions(
+        &mut self,
+        option: &CollisionMatrixGenerationOption,
+    ) -> Result<HashMap<(usize, usize), DisabledReason>> {
+        let mut reasons = HashMap::new();
+
+        // links without any collision geometry can never be in collision
+        let links_with_geometry: Vec<usize> = self
+            .urdf_robot
+            .links
+            .iter()
+            .enumerate()
+            .filter(|(_, link)| !link.collision.is_empty())
+            .map(|(idx, _)| idx)
+            .collect();
+
+        for joint in &self.urdf_robot.joints {
+            let (a, b) = (
+                self.link_index(&joint.parent.link)?,
+                self.link_index(&joint.child.link)?,
+            );
+            reasons.insert((a.min(b), a.max(b)), DisabledReason::Adjacent);
+        }
+
+        let limits: Vec<(f32, f32)> = self
+            .robot_chain
+            .iter_joints()
+            .map(|joint| match joint.limits {
+                Some(limit) => (limit.min, limit.max),
+                None => (-std::f32::consts::PI, std::f32::consts::PI),
+            })
+            .collect();
+
+        // default configuration: zero, or the closest value to zero within the limits
+        let default_joints: Vec<f32> = limits
+            .iter()
+            .map(|(min, max)| 0.0_f32.clamp(*min, *max))
+            .collect();
+        self.set_joints(&default_joints)?;
+        self.has_collision()?;
+        for pair in self.colliding_link_pairs() {
+            reasons.entry(pair).or_insert(DisabledReason::Default);
+        }
+
+        let mut rng = SmallRng::seed_from_u64(option.seed);
+        let mut collision_counts: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut joints = vec![0.0; limits.len()];
+        for _ in 0..option.num_samples {
+            for (joint, (min, max)) in joints.iter_mut().zip(&limits) {
+                *joint = if min < max {
+                    rng.gen_range(*min..*max)
+                } else {
+                    *min
+                };
+            }
+            self.set_joints(&joints)?;
+            self.has_collision()?;
+            for pair in self.colliding_link_pairs() {
+                *collision_counts.entry(pair).or_default() += 1;
+            }
+        }
+
+        let always_threshold = option.min_collision_fraction * option.num_samples as f32;
+        let candidate_pairs = links_with_geometry
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| links_with_geometry[i + 1..].iter().map(|b| (*a, *b)));
+        for pair in candidate_pairs {
+            if reasons.contains_key(&pair) {
+                continue;
+            }
+            match collision_counts.get(&pair) {
+                None => {
+                    reasons.insert(pair, DisabledReason::Never);
+                }
+                Some(count) if *count as f32 >= always_threshold => {
+                    reasons.insert(pair, DisabledReason::Always);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(reasons)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::{arm, SELF_COLLIDING};
+    use crate::robot::CollisionResult;
+
+    #[test]
+    fn disables_adjacent_and_never_colliding_pairs() {
+        let mut robot = arm();
+        robot.set_joints(&SELF_COLLIDING).unwrap();
+        let option = CollisionMatrixGenerationOption {
+            num_samples: 5000,
+            ..Default::default()
+        };
+        let pairs = robot.generate_disabled_collisions(&option).unwrap();
+
+        let reason_of = |link1: &str, link2: &str| {
+            pairs
+                .iter()
+                .find(|pair| pair.link1 == link1 && pair.link2 == link2)
+                .map(|pair| pair.reason.as_deref().unwrap())
+        };
+        assert_eq!(reason_of("base", "link1"), Some("Adjacent"));
+        assert_eq!(reason_of("link1", "link2"), Some("Adjacent"));
+        assert_eq!(reason_of("link2", "slider"), Some("Adjacent"));
+        // link2 is too short to reach the base, unlike the slider
+        assert_eq!(reason_of("base", "link2"), Some("Never"));
+        // the slider can (rarely) enter link1
+        assert_eq!(reason_of("link1", "slider"), None);
+
+        // the robot is left as it was
+        assert_eq!(robot.robot_chain.joint_positions(), SELF_COLLIDING);
+        assert!(!robot.collision_checker.allowed_collisions.is_allowed(0, 2));
+        assert_eq!(
+            robot.has_collision().unwrap(),
+            CollisionResult::SelfCollision
+        );
+
+        robot.apply_disabled_collisions(&pairs);
+        assert!(robot.collision_checker.allowed_collisions.is_allowed(0, 2));
+        assert_eq!(
+            robot.has_collision().unwrap(),
+            CollisionResult::SelfCollision
+        );
+    }
+
+    #[test]
+    fn needs_samples() {
+        let mut robot = arm();
+        let option = CollisionMatrixGenerationOption {
+            num_samples: 0,
+            ..Default::default()
+        };
+        assert!(robot.generate_disabled_collisions(&option).is_err());
+    }
+}
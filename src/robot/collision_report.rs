@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use rapier3d::math::{Point, Real, Vector};
+use rapier3d::prelude::ColliderHandle;
+
+use super::{ContactKind, Robot};
+use crate::collision_checker::ColliderOwner;
+
+/// A single contact point between two bodies, in the world frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactPoint {
+    /// Contact point on the first body.
+    pub point1: Point<Real>,
+    /// Contact point on the second body.
+    pub point2: Point<Real>,
+    /// Unit normal pointing from the first body towards the second.
+    pub normal: Vector<Real>,
+    /// Signed distance along the normal; negative when penetrating.
+    pub distance: Real,
+}
+
+/// All contacts between two bodies (links or obstacles) that are in collision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkContact {
+    /// Name of the first link (or attached object).
+    pub name1: String,
+    /// Name of the second link (or attached object), or of the obstacle for environment
+    /// contacts.
+    pub name2: String,
+    pub kind: ContactKind,
+    /// Largest penetration depth among the contacts (zero if merely touching).
+    pub depth: Real,
+    pub contacts: Vec<ContactPoint>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollisionReport {
+    /// One entry per colliding pair, sorted by kind and names.
+    pub pairs: Vec<LinkContact>,
+}
+
+impl CollisionReport {
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// The pair with the deepest penetration.
+    pub fn deepest(&self) -> Option<&LinkContact> {
+        self.pairs.iter().max_by(|a, b| a.depth.total_cmp(&b.depth))
+    }
+}
+
+impl Robot {
+    /// Describes the collisions found by the last call to [`Robot::has_collision`].
+    /// Contact points and distances are those of the padded links.
+    ///
+    /// This only reads the contacts that were already computed, so it does not reflect
+    /// joint or obstacle changes made since then.
+    pub fn collision_report(&self) -> CollisionReport {
+        let mut pairs: HashMap<(ContactKind, &str, &str), LinkContact> = HashMap::new();
+
+        for pair in self
+            .collision_checker
+            .narrow_phase
+            .contact_graph()
+            .interactions()
+        {
+            if !pair.has_any_active_contact {
+                continue;
+            }
+            let (Some((owner1, name1)), Some((owner2, name2))) = (
+                self.collider_owner_name(pair.collider1),
+                self.collider_owner_name(pair.collider2),
+            ) else {
+                continue;
+            };
+
+            // links first (in link order), then attached objects, then obstacles
+            let flip = match (owner1, owner2) {
+                (ColliderOwner::Link(a), ColliderOwner::Link(b)) => a > b,
+                (ColliderOwner::Attached(_), ColliderOwner::Link(_)) => true,
+                (ColliderOwner::Attached(a), ColliderOwner::Attached(b)) => a > b,
+                (ColliderOwner::Environment, _) => true,
+                _ => false,
+            };
+            let kind =
+                if owner1 == ColliderOwner::Environment || owner2 == ColliderOwner::Environment {
+                    ContactKind::Environment
+                } else {
+                    ContactKind::SelfCollision
+                };
+            let (name1, name2) = if flip { (name2, name1) } else { (name1, name2) };
+
+            let entry = pairs
+                .entry((kind, name1, name2))
+                .or_insert_with(|| LinkContact {
+                    name1: name1.to_owned(),
+                    name2: name2.to_owned(),
+                    kind,
+                    depth: 0.0,
+                    contacts: Vec::new(),
+                });
+
+            let skin_of = |handle| {
+                self.collision_checker
+                    .collider_set
+                    .get(handle)
+                    .map_or(0.0, |collider| collider.contact_skin())
+            };
+            let (skin1, skin2) = (skin_of(pair.collider1), skin_of(pair.collider2));
+
+            for manifold in &pair.manifolds {
+                let normal = manifold.data.normal;
+                // solver contacts are the active ones, located halfway between the contact
+                // points of the exact shapes, with the padding (skin) already subtracted
+                // from their distance
+                for contact in &manifold.data.solver_contacts {
+                    let half = normal * ((contact.dist + skin1 + skin2) / 2.0);
+                    let point1 = contact.point - half + normal * skin1;
+                    let point2 = contact.point + half - normal * skin2;
+                    let contact = if flip {
+                        ContactPoint {
+                            point1: point2,
+                            point2: point1,
+                            normal: -normal,
+                            distance: contact.dist,
+                        }
+                    } else {
+                        ContactPoint {
+                            point1,
+                            point2,
+                            normal,
+                            distance: contact.dist,
+                        }
+                    };
+                    entry.depth = entry.depth.max(-contact.distance);
+                    entry.contacts.push(contact);
+                }
+            }
+        }
+
+        let mut pairs: Vec<_> = pairs.into_values().collect();
+        pairs.sort_by(|a, b| {
+            (a.kind == ContactKind::Environment, &a.name1, &a.name2).cmp(&(
+                b.kind == ContactKind::Environment,
+                &b.name1,
+                &b.name2,
+            ))
+        });
+        CollisionReport { pairs }
+    }
+
+    /// The owner of a collider, together with the name of the link or obstacle.
+    pub(crate) fn collider_owner_name(
+        &self,
+        handle: ColliderHandle,
+    ) -> Option<(ColliderOwner, &str)> {
+        let collider = self.collision_checker.collider_set.get(handle)?;
+        let owner = ColliderOwner::from_user_data(collider.user_data);
+        let name = match owner {
+            ColliderOwner::Link(idx) => self.urdf_robot.links.get(idx)?.name.as_str(),
+            ColliderOwner::Environment => self
+                .obstacles
+                .iter()
+                .find(|(_, obstacle)| obstacle.contains(handle))?
+                .0
+                .as_str(),
+            ColliderOwner::Attached(_) => self.attached_object_of(handle)?,
+        };
+        Some((owner, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::{arm, at, ball, SELF_COLLIDING};
+
+    #[test]
+    fn reports_self_collisions_allowed_by_the_matrix_only() {
+        let mut robot = arm();
+        robot.set_joints(&SELF_COLLIDING).unwrap();
+        robot.has_collision().unwrap();
+
+        let report = robot.collision_report();
+        assert_eq!(report.pairs.len(), 1);
+        let pair = &report.pairs[0];
+        assert_eq!(
+            (pair.name1.as_str(), pair.name2.as_str(), pair.kind),
+            ("link1", "slider", ContactKind::SelfCollision)
+        );
+        assert!(pair.depth > 0.0);
+        assert!(!pair.contacts.is_empty());
+
+        robot.allow_collision("link1", "slider").unwrap();
+        robot.has_collision().unwrap();
+        assert!(robot.collision_report().is_empty());
+    }
+
+    #[test]
+    fn reports_environment_contacts_from_the_link() {
+        let mut robot = arm();
+        // 0.02 into the slider, along x
+        robot
+            .add_obstacle("obstacle", &ball(0.05), at([0.48, 0.0, 0.6]))
+            .unwrap();
+        robot.has_collision().unwrap();
+
+        let report = robot.collision_report();
+        assert_eq!(report.pairs.len(), 1);
+        let pair = report.deepest().unwrap();
+        assert_eq!(
+            (pair.name1.as_str(), pair.name2.as_str(), pair.kind),
+            ("slider", "obstacle", ContactKind::Environment)
+        );
+        assert!((pair.depth - 0.02).abs() < 1e-4);
+        for contact in &pair.contacts {
+            assert!((contact.normal - Vector::x()).norm() < 1e-4);
+            assert!((contact.distance + 0.02).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Sanity check that a robot's collision geometry lines up with its visual geometry,
+//! which catches e.g. meshes in the wrong units, wrong mirroring or wrong origins.
+
+use eyre::{OptionExt, Result};
+use rapier3d::math::Real;
+use rapier3d::parry::bounding_volume::{Aabb, BoundingVolume};
+
+use super::{geometry_to_colliders, MeshColliderMode, Robot};
+
+/// A link whose collision geometry does not match its visual geometry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeometryMismatch {
+    pub link: String,
+    /// Bounding box of the visual geometry, in the link frame.
+    pub visual_aabb: Aabb,
+    /// Bounding box of the collision geometry, in the link frame.
+    pub collision_aabb: Aabb,
+    /// Largest difference between the corners of the two bounding boxes.
+    pub difference: Real,
+}
+
+impl Robot {
+    /// Compares, for every link that has both, the bounding boxes of its visual and of its
+    /// collision geometry, and returns the links where they differ by more than `tolerance`.
+    ///
+    /// Collision geometry is often a simplified version of the visual one, so the
+    /// tolerance should account for that.
+    pub fn check_geometry_consistency(&self, tolerance: Real) -> Result<Vec<GeometryMismatch>> {
+        let base_dir = self.base_dir.as_deref();
+
+        let mut mismatches = Vec::new();
+        for link in &self.urdf_robot.links {
+            let visual_aabb = link
+                .visual
+                .iter()
+                .flat_map(|visual| {
+                    geometry_to_colliders(
+                        &base_dir,
+                        &visual.geometry,
+                        &visual.origin,
+                        MeshColliderMode::TriMesh,
+                    )
+                })
+                .map(|builder| builder.shape.compute_aabb(&builder.position))
+                .reduce(|a, b| a.merged(&b));
+
+            let mut collision_aabb: Option<Aabb> = None;
+            for handle in self.colliders.get(&link.name).into_iter().flatten() {
+                let collider = self
+                    .collision_checker
+                    .collider_set
+                    .get(*handle)
+                    .ok_or_eyre("cannot find collider")?;
+                let offset = self
+                    .collider_offsets
+                    .get(handle)
+                    .ok_or_eyre("cannot find collider offset")?;
+                let aabb = collider.shape().compute_aabb(offset);
+                collision_aabb = Some(collision_aabb.map_or(aabb, |merged| merged.merged(&aabb)));
+            }
+
+            let (Some(visual_aabb), Some(collision_aabb)) = (visual_aabb, collision_aabb) else {
+                continue;
+            };
+            let difference = (visual_aabb.mins - collision_aabb.mins)
+                .abs()
+                .max()
+                .max((visual_aabb.maxs - collision_aabb.maxs).abs().max());
+            if difference > tolerance {
+                mismatches.push(GeometryMismatch {
+                    link: link.name.clone(),
+                    visual_aabb,
+                    collision_aabb,
+                    difference,
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::cube_stl;
+
+    #[test]
+    fn reports_links_whose_geometries_differ() {
+        let mesh = cube_stl("consistency");
+        let urdf = format!(
+            r#"<robot name="mismatched">
+  <link name="base">
+    <visual><geometry><box size="0.2 0.2 0.2" /></geometry></visual>
+    <collision><geometry><box size="0.2 0.2 0.2" /></geometry></collision>
+  </link>
+  <link name="padded">
+    <visual><geometry><box size="0.1 0.1 0.1" /></geometry></visual>
+    <collision><geometry><box size="0.3 0.1 0.1" /></geometry></collision>
+  </link>
+  <link name="millimeters">
+    <visual><geometry><mesh filename="{mesh}" scale="0.001 0.001 0.001" /></geometry></visual>
+    <collision><geometry><mesh filename="{mesh}" /></geometry></collision>
+  </link>
+  <joint name="fixed1" type="fixed">
+    <parent link="base" />
+    <child link="padded" />
+  </joint>
+  <joint name="fixed2" type="fixed">
+    <parent link="padded" />
+    <child link="millimeters" />
+  </joint>
+</robot>"#
+        );
+        let robot =
+            Robot::from_urdf_robot(urdf_rs::read_from_string(&urdf).unwrap(), None).unwrap();
+
+        let mismatches = robot.check_geometry_consistency(0.05).unwrap();
+        let links: Vec<_> = mismatches.iter().map(|m| m.link.as_str()).collect();
+        assert_eq!(links, ["padded", "millimeters"]);
+        assert!((mismatches[0].difference - 0.1).abs() < 1e-5);
+        assert!((mismatches[1].difference - 0.4995).abs() < 1e-5);
+
+        let mismatches = robot.check_geometry_consistency(0.2).unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].link, "millimeters");
+    }
+}
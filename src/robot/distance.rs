@@ -0,0 +1,243 @@
+use eyre::{OptionExt, Result};
+use rapier3d::math::{Point, Real, Vector};
+use rapier3d::parry::query::{self, ClosestPoints};
+use rapier3d::prelude::{Collider, ColliderHandle};
+
+use super::{ContactKind, Robot};
+use crate::collision_checker::ColliderOwner;
+
+/// Minimum distance between two bodies (links or obstacles), in the world frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairDistance {
+    pub name1: String,
+    pub name2: String,
+    pub kind: ContactKind,
+    /// What the first body is, which (unlike its name) is unique within the robot.
+    pub owner1: ColliderOwner,
+    pub owner2: ColliderOwner,
+    /// Signed distance; negative when the two bodies are penetrating.
+    pub distance: Real,
+    /// Witness point on the first body.
+    pub point1: Point<Real>,
+    /// Witness point on the second body.
+    pub point2: Point<Real>,
+    /// Unit normal pointing from the first body towards the second.
+    pub normal: Vector<Real>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DistanceReport {
+    /// One entry for every link pair (and link/obstacle pair) that is collision checked.
+    pub pairs: Vec<PairDistance>,
+}
+
+impl DistanceReport {
+    /// The pair with the smallest signed distance.
+    pub fn min_distance(&self) -> Option<&PairDistance> {
+        self.pairs
+            .iter()
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+}
+
+/// A named set of colliders that move together.
+struct Body<'a> {
+    name: &'a str,
+    owner: ColliderOwner,
+    handles: Vec<ColliderHandle>,
+}
+
+/// Signed distance, witness points and normal between two colliders, taking their padding
+/// (contact skin) into account.
+fn collider_distance(
+    c1: &Collider,
+    c2: &Collider,
+) -> Result<(Real, Point<Real>, Point<Real>, Vector<Real>)> {
+    let (distance, point1, point2, normal) = shape_distance(c1, c2)?;
+    let (skin1, skin2) = (c1.contact_skin(), c2.contact_skin());
+    Ok((
+        distance - skin1 - skin2,
+        point1 + normal * skin1,
+        point2 - normal * skin2,
+        normal,
+    ))
+}
+
+/// Signed distance, witness points and normal between the exact shapes of two colliders.
+fn shape_distance(
+    c1: &Collider,
+    c2: &Collider,
+) -> Result<(Real, Point<Real>, Point<Real>, Vector<Real>)> {
+    let (pos1, shape1) = (c1.position(), c1.shape());
+    let (pos2, shape2) = (c2.position(), c2.shape());
+
+    let distance = query::distance(pos1, shape1, pos2, shape2)?;
+
+    if distance <= 0.0 {
+        // penetrating: the contact gives us the penetration depth
+        let contact = query::contact(pos1, shape1, pos2, shape2, 0.0)?
+            .ok_or_eyre("failed to compute penetration between intersecting shapes")?;
+        return Ok((
+            contact.dist.min(0.0),
+            contact.point1,
+            contact.point2,
+            contact.normal1.into_inner(),
+        ));
+    }
+
+    // the margin only needs to be slightly larger than the known distance
+    match query::closest_points(pos1, shape1, pos2, shape2, distance * 1.01 + 1e-6)? {
+        ClosestPoints::WithinMargin(point1, point2) => {
+            let normal = (point2 - point1) / distance;
+            Ok((distance, point1, point2, normal))
+        }
+        _ => Err(eyre::eyre!(
+            "failed to compute closest points between separated shapes"
+        )),
+    }
+}
+
+impl Robot {
+    /// Computes the minimum signed distance, witness points and normal for every pair of
+    /// links (and attached objects) that is not excluded from collision checking, and for
+    /// every link/obstacle pair.
+    /// Distances are between the padded links.
+    pub fn distance_report(&mut self) -> Result<DistanceReport> {
+        self.update_link_colliders()?;
+
+        let mut bodies: Vec<Body> = self
+            .urdf_robot
+            .links
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, link)| {
+                let handles = self.colliders.get(&link.name)?;
+                (!handles.is_empty()).then(|| Body {
+                    name: &link.name,
+                    owner: ColliderOwner::Link(idx),
+                    handles: handles.clone(),
+                })
+            })
+            .collect();
+        bodies.extend(self.attached_objects.iter().map(|(name, object)| Body {
+            name,
+            owner: ColliderOwner::Attached(object.id),
+            handles: object.colliders.iter().map(|(h, _)| *h).collect(),
+        }));
+        bodies.extend(self.obstacles.iter().map(|(name, obstacle)| Body {
+            name,
+            owner: ColliderOwner::Environment,
+            handles: obstacle.colliders.iter().map(|(h, _)| *h).collect(),
+        }));
+
+        let collider_set = &self.collision_checker.collider_set;
+        let allowed_collisions = &self.collision_checker.allowed_collisions;
+
+        let mut pairs = Vec::new();
+        for (i, body1) in bodies.iter().enumerate() {
+            for body2 in &bodies[i + 1..] {
+                if !allowed_collisions.can_collide(body1.owner, body2.owner) {
+                    continue;
+                }
+
+                let mut closest: Option<(Real, Point<Real>, Point<Real>, Vector<Real>)> = None;
+                for h1 in &body1.handles {
+                    for h2 in &body2.handles {
+                        let c1 = collider_set.get(*h1).ok_or_eyre("cannot find collider")?;
+                        let c2 = collider_set.get(*h2).ok_or_eyre("cannot find collider")?;
+                        let result = collider_distance(c1, c2)?;
+                        if closest.is_none_or(|(distance, ..)| result.0 < distance) {
+                            closest = Some(result);
+                        }
+                    }
+                }
+
+                if let Some((distance, point1, point2, normal)) = closest {
+                    let kind = if body2.owner == ColliderOwner::Environment {
+                        ContactKind::Environment
+                    } else {
+                        ContactKind::SelfCollision
+                    };
+                    pairs.push(PairDistance {
+                        name1: body1.name.to_owned(),
+                        name2: body2.name.to_owned(),
+                        kind,
+                        owner1: body1.owner,
+                        owner2: body2.owner,
+                        distance,
+                        point1,
+                        point2,
+                        normal,
+                    });
+                }
+            }
+        }
+
+        Ok(DistanceReport { pairs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::{arm, at, cuboid};
+
+    #[test]
+    fn reports_distances_and_witness_points() {
+        let mut robot = arm();
+        // 0.1 in front of the slider (whose surface is at x = 0.45)
+        robot
+            .add_obstacle("box", &cuboid(0.1, 0.1, 0.1), at([0.6, 0.0, 0.6]))
+            .unwrap();
+        let report = robot.distance_report().unwrap();
+
+        // adjacent links are never checked
+        let pair_names: Vec<_> = report
+            .pairs
+            .iter()
+            .map(|pair| (pair.name1.as_str(), pair.name2.as_str()))
+            .collect();
+        for adjacent in [("base", "link1"), ("link1", "link2"), ("link2", "slider")] {
+            assert!(!pair_names.contains(&adjacent));
+        }
+        // 3 pairs of links, and 4 links against the box
+        assert_eq!(report.pairs.len(), 3 + 4);
+
+        let closest = report.min_distance().unwrap();
+        assert_eq!(
+            (closest.name1.as_str(), closest.name2.as_str()),
+            ("slider", "box")
+        );
+        assert_eq!(closest.kind, ContactKind::Environment);
+        assert_eq!(closest.owner1, ColliderOwner::Link(3));
+        assert_eq!(closest.owner2, ColliderOwner::Environment);
+        assert!((closest.distance - 0.1).abs() < 1e-5);
+        assert!((closest.point1 - Point::new(0.45, 0.0, 0.6)).norm() < 1e-5);
+        assert!((closest.point2 - Point::new(0.55, 0.0, 0.6)).norm() < 1e-5);
+        assert!((closest.normal - Vector::x()).norm() < 1e-5);
+
+        let link_pair = report
+            .pairs
+            .iter()
+            .find(|pair| (pair.name1.as_str(), pair.name2.as_str()) == ("link1", "slider"))
+            .unwrap();
+        assert_eq!(link_pair.kind, ContactKind::SelfCollision);
+        assert!((link_pair.distance - 0.3).abs() < 1e-5);
+    }
+
+    #[test]
+    fn reports_penetration_as_negative_distance() {
+        let mut robot = arm();
+        // 0.02 into the slider
+        robot
+            .add_obstacle("box", &cuboid(0.1, 0.1, 0.1), at([0.48, 0.0, 0.6]))
+            .unwrap();
+        let report = robot.distance_report().unwrap();
+        let closest = report.min_distance().unwrap();
+        assert_eq!(
+            (closest.name1.as_str(), closest.name2.as_str()),
+            ("slider", "box")
+        );
+        assert!((closest.distance + 0.02).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,358 @@
+//! Inverse kinematics, with k's Jacobian solver on the serial chain from the root to the
+//! target link.
+
+use eyre::{ensure, Result};
+use k::{InverseKinematicsSolver, JacobianIkSolver, SerialChain};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use rapier3d::math::{Isometry, Point, Real};
+
+use super::{rapier_isometry_to_k, CollisionResult, Robot, RobotError};
+
+/// What the target link has to reach, in the world frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IkTarget {
+    /// Only the position of the link's origin is constrained: any orientation will do.
+    Position(Point<Real>),
+    /// Position and orientation of the link.
+    Pose {
+        pose: Isometry<Real>,
+        /// Allowed angle (in radians) between the link and the target orientation.
+        /// Increase it for targets whose orientation only matters roughly.
+        angle_tolerance: Real,
+    },
+}
+
+impl IkTarget {
+    pub const DEFAULT_ANGLE_TOLERANCE: Real = 0.005;
+
+    /// A pose target with the default angle tolerance.
+    pub fn pose(pose: Isometry<Real>) -> Self {
+        IkTarget::Pose {
+            pose,
+            angle_tolerance: Self::DEFAULT_ANGLE_TOLERANCE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IkOption {
+    /// Allowed distance between the link and the target position.
+    pub position_tolerance: Real,
+    /// Maximum number of iterations of each attempt.
+    pub max_iterations: usize,
+    /// Number of attempts from random configurations (within the joint limits) after the
+    /// first attempt from the seed fails.
+    pub num_restarts: usize,
+    /// Configuration (of the whole robot) the first attempt starts from. Defaults to the
+    /// current joint positions.
+    pub seed: Option<Vec<f32>>,
+    /// Rejects solutions that are in collision (see [`Robot::has_collision`]), and tries
+    /// again from another random configuration.
+    pub reject_colliding: bool,
+    /// Seed of the random restarts, so that solutions are reproducible.
+    pub rng_seed: u64,
+}
+
+impl Default for IkOption {
+    fn default() -> Self {
+        Self {
+            position_tolerance: 0.001,
+            max_iterations: 100,
+            num_restarts: 10,
+            seed: None,
+            reject_colliding: false,
+            rng_seed: 42,
+        }
+    }
+}
+
+impl Robot {
+    /// Finds joint positions for which `link_name` reaches the target, and sets them on
+    /// the robot. Returns the joint positions of the whole robot, or `None` if no attempt
+    /// succeeded. Unless a solution is returned (i.e. also on error), the joint positions
+    /// are left unchanged.
+    pub fn inverse_kinematics(
+        &mut self,
+        link_name: &str,
+        target: &IkTarget,
+        option: &IkOption,
+    ) -> Result<Option<Vec<f32>>> {
+        let original_joints = self.robot_chain.joint_positions();
+        let result = self.solve_ik(link_name, target, option, &original_joints);
+        if !matches!(result, Ok(Some(_))) {
+            // as-is, even if the original positions are out of the joint limits
+            self.robot_chain
+                .set_joint_positions_unchecked(&original_joints);
+        }
+        result
+    }
+
+    fn solve_ik(
+        &mut self,
+        link_name: &str,
+        target: &IkTarget,
+        option: &IkOption,
+        original_joints: &[f32],
+    ) -> Result<Option<Vec<f32>>> {
+        if let Some(seed) = &option.seed {
+            ensure!(
+                seed.len() == original_joints.len(),
+                "Expected a seed of {} joint values, got {}",
+                original_joints.len(),
+                seed.len()
+            );
+        }
+
+        let end = self
+            .robot_chain
+            .find_link(link_name)
+            .ok_or_else(|| RobotError::UnknownLink(link_name.to_owned()))?;
+        // the serial chain shares its nodes with the robot's chain
+        let arm = SerialChain::from_end(end);
+
+        let (target_pose, constraints, angle_tolerance) = match target {
+            IkTarget::Position(position) => (
+                Isometry::translation(position.x, position.y, position.z),
+                k::Constraints {
+                    rotation_x: false,
+                    rotation_y: false,
+                    rotation_z: false,
+                    ..Default::default()
+                },
+                // the orientation is not constrained
+                Real::INFINITY,
+            ),
+            IkTarget::Pose {
+                pose,
+                angle_tolerance,
+            } => {
+                ensure!(
+                    *angle_tolerance > 0.0,
+                    "Angle tolerance must be positive (got {})",
+                    angle_tolerance
+                );
+                (*pose, k::Constraints::default(), *angle_tolerance)
+            }
+        };
+        // the chain is relative to the robot's base
+        let target_pose = rapier_isometry_to_k(&(self.base_pose.inverse() * target_pose));
+        let solver = JacobianIkSolver::new(
+            option.position_tolerance,
+            angle_tolerance,
+            0.5,
+            option.max_iterations,
+        );
+
+        let limits: Vec<(f32, f32)> = arm
+            .iter_joints()
+            .map(|joint| {
+                joint
+                    .limits
+                    .map_or((-std::f32::consts::PI, std::f32::consts::PI), |limit| {
+                        (limit.min, limit.max)
+                    })
+            })
+            .collect();
+        let mut rng = SmallRng::seed_from_u64(option.rng_seed);
+
+        let seed = option.seed.as_deref().unwrap_or(original_joints);
+        self.set_joints(seed)?;
+        for attempt in 0..=option.num_restarts {
+            if attempt > 0 {
+                let random_joints: Vec<f32> = limits
+                    .iter()
+                    .map(|(min, max)| {
+                        if min < max {
+                            rng.gen_range(*min..*max)
+                        } else {
+                            *min
+                        }
+                    })
+                    .collect();
+                arm.set_joint_positions_clamped(&random_joints);
+            }
+
+            if solver
+                .solve_with_constraints(&arm, &target_pose, &constraints)
+                .is_err()
+            {
+                continue;
+            }
+            // make sure the solution is within the joint limits
+            let joints = self.robot_chain.joint_positions();
+            if self.set_joints(&joints).is_err() {
+                continue;
+            }
+            if option.reject_colliding && self.has_collision()? != CollisionResult::Free {
+                continue;
+            }
+            return Ok(Some(joints));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::{arm, at, ball, slider_position};
+
+    /// An arm with a spherical wrist, whose tool reaches any pose close to the start one.
+    const WRIST_URDF: &str = r#"<robot name="wrist">
+  <link name="base" />
+  <link name="link1" />
+  <link name="link2" />
+  <link name="link3" />
+  <link name="link4" />
+  <link name="link5" />
+  <link name="tool" />
+  <joint name="joint1" type="revolute">
+    <parent link="base" />
+    <child link="link1" />
+    <origin xyz="0 0 0.1" />
+    <axis xyz="0 0 1" />
+    <limit lower="-3" upper="3" effort="0" velocity="0" />
+  </joint>
+  <joint name="joint2" type="revolute">
+    <parent link="link1" />
+    <child link="link2" />
+    <origin xyz="0 0 0.2" />
+    <axis xyz="0 1 0" />
+    <limit lower="-3" upper="3" effort="0" velocity="0" />
+  </joint>
+  <joint name="joint3" type="revolute">
+    <parent link="link2" />
+    <child link="link3" />
+    <origin xyz="0 0 0.4" />
+    <axis xyz="0 1 0" />
+    <limit lower="-3" upper="3" effort="0" velocity="0" />
+  </joint>
+  <joint name="joint4" type="revolute">
+    <parent link="link3" />
+    <child link="link4" />
+    <origin xyz="0 0 0.3" />
+    <axis xyz="0 0 1" />
+    <limit lower="-3" upper="3" effort="0" velocity="0" />
+  </joint>
+  <joint name="joint5" type="revolute">
+    <parent link="link4" />
+    <child link="link5" />
+    <axis xyz="0 1 0" />
+    <limit lower="-3" upper="3" effort="0" velocity="0" />
+  </joint>
+  <joint name="joint6" type="revolute">
+    <parent link="link5" />
+    <child link="tool" />
+    <origin xyz="0 0 0.1" />
+    <axis xyz="0 0 1" />
+    <limit lower="-3" upper="3" effort="0" velocity="0" />
+  </joint>
+</robot>"#;
+
+    #[test]
+    fn reaches_a_pose_within_the_angle_tolerance() {
+        let mut robot =
+            Robot::from_urdf_robot(urdf_rs::read_from_string(WRIST_URDF).unwrap(), None).unwrap();
+        let goal = [0.3, 0.5, 0.8, 0.2, 0.6, 0.1];
+        let target = robot.link_pose(&goal, "tool").unwrap();
+        let option = IkOption {
+            seed: Some(goal.iter().map(|q| q + 0.1).collect()),
+            ..Default::default()
+        };
+
+        let joints = robot
+            .inverse_kinematics("tool", &IkTarget::pose(target), &option)
+            .unwrap()
+            .unwrap();
+        assert_eq!(joints, robot.robot_chain.joint_positions());
+        let pose = robot.link_pose(&joints, "tool").unwrap();
+        assert!((pose.translation.vector - target.translation.vector).norm() < 1.5e-3);
+        assert!(
+            pose.rotation.angle_to(&target.rotation) < IkTarget::DEFAULT_ANGLE_TOLERANCE + 1e-3
+        );
+
+        let target = IkTarget::Pose {
+            pose: target,
+            angle_tolerance: 0.0,
+        };
+        assert!(robot.inverse_kinematics("tool", &target, &option).is_err());
+    }
+
+    #[test]
+    fn reaches_a_position_with_any_orientation() {
+        let mut robot = arm();
+        let target = Point::from(slider_position(&[0.7, 0.3, 0.4]));
+
+        let joints = robot
+            .inverse_kinematics("slider", &IkTarget::Position(target), &IkOption::default())
+            .unwrap()
+            .unwrap();
+        let pose = robot.link_pose(&joints, "slider").unwrap();
+        assert!((pose.translation.vector - target.coords).norm() < 1.5e-3);
+    }
+
+    #[test]
+    fn leaves_the_joints_unchanged_without_a_solution() {
+        let mut robot = arm();
+        robot.set_joints(&[0.1, 0.2, 0.3]).unwrap();
+        let option = IkOption {
+            num_restarts: 3,
+            ..Default::default()
+        };
+
+        // out of reach
+        let target = IkTarget::Position(Point::new(5.0, 0.0, 0.0));
+        assert_eq!(
+            robot
+                .inverse_kinematics("slider", &target, &option)
+                .unwrap(),
+            None
+        );
+        assert_eq!(robot.robot_chain.joint_positions(), [0.1, 0.2, 0.3]);
+
+        let option = IkOption {
+            seed: Some(vec![0.0; 2]),
+            ..option
+        };
+        assert!(robot
+            .inverse_kinematics("slider", &target, &option)
+            .is_err());
+        assert!(robot
+            .inverse_kinematics("no_such_link", &target, &IkOption::default())
+            .is_err());
+        assert_eq!(robot.robot_chain.joint_positions(), [0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn rejects_colliding_solutions() {
+        let mut robot = arm();
+        let position = slider_position(&[0.7, 0.3, 0.4]);
+        let target = IkTarget::Position(Point::from(position));
+        // every solution puts the slider inside the obstacle
+        robot
+            .add_obstacle("ball", &ball(0.1), at(position))
+            .unwrap();
+        let option = IkOption {
+            num_restarts: 3,
+            ..Default::default()
+        };
+
+        assert!(robot
+            .inverse_kinematics("slider", &target, &option)
+            .unwrap()
+            .is_some());
+        robot.set_joints(&[0.0; 3]).unwrap();
+        let option = IkOption {
+            reject_colliding: true,
+            ..option
+        };
+        assert_eq!(
+            robot
+                .inverse_kinematics("slider", &target, &option)
+                .unwrap(),
+            None
+        );
+        assert_eq!(robot.robot_chain.joint_positions(), [0.0; 3]);
+    }
+}
@@ -0,0 +1,331 @@
+//! Geometric Jacobians of the links, and manipulability metrics derived from them.
+
+use std::str::FromStr;
+
+use eyre::{ensure, ContextCompat, Result};
+use k::SerialChain;
+use rapier3d::math::{Isometry, Real, Vector};
+use rapier3d::na::DMatrix;
+use rayon::prelude::*;
+
+use super::{k_isometry_to_rapier, set_chain_joints, Robot, RobotError};
+
+/// Frame in which the velocities of a Jacobian are expressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JacobianFrame {
+    /// The world frame (i.e. the base frame rotated by the robot's base pose).
+    #[default]
+    World,
+    /// The frame of the link itself.
+    Local,
+}
+
+impl FromStr for JacobianFrame {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "world" => Ok(JacobianFrame::World),
+            "local" => Ok(JacobianFrame::Local),
+            _ => Err(eyre::eyre!(
+                "Unknown Jacobian frame '{}' (expected 'world' or 'local')",
+                s
+            )),
+        }
+    }
+}
+
+/// How far a Jacobian is from being singular, from its singular values.
+///
+/// For a link, only the columns of the joints between the root and the link are used (see
+/// [`Robot::manipulability`]): the columns of the other joints are zero, and would make
+/// every link moved by fewer joints than the robot has look singular.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Manipulability {
+    /// Yoshikawa's manipulability, i.e. the product of the singular values.
+    pub manipulability: Real,
+    /// Ratio of the largest to the smallest singular value (infinite when singular).
+    pub condition_number: Real,
+    /// Smallest singular value, i.e. the distance to the closest singularity.
+    pub min_singular_value: Real,
+}
+
+impl Manipulability {
+    /// Metrics of the given Jacobian, as is. A Jacobian with NaN entries (e.g. of a
+    /// configuration that violates the joint limits) gives NaN metrics.
+    pub fn from_jacobian(jacobian: &DMatrix<Real>) -> Self {
+        if jacobian.iter().any(|value| value.is_nan()) {
+            return Self {
+                manipulability: Real::NAN,
+                condition_number: Real::NAN,
+                min_singular_value: Real::NAN,
+            };
+        }
+        // e.g. the root link, which no joint moves
+        if jacobian.is_empty() {
+            return Self {
+                manipulability: 0.0,
+                condition_number: Real::INFINITY,
+                min_singular_value: 0.0,
+            };
+        }
+        let singular_values = jacobian.singular_values();
+        let max = singular_values.max();
+        let min = singular_values.min();
+        Self {
+            manipulability: singular_values.product(),
+            condition_number: if min > 0.0 { max / min } else { Real::INFINITY },
+            min_singular_value: min,
+        }
+    }
+}
+
+/// Column, in the Jacobians of the whole chain, of each joint of the serial chain of a link.
+fn joint_columns(chain: &k::Chain<f32>, arm: &SerialChain<f32>) -> Result<Vec<usize>> {
+    let joint_names: Vec<String> = chain.iter_joints().map(|j| j.name.clone()).collect();
+    arm.iter_joints()
+        .map(|joint| {
+            joint_names
+                .iter()
+                .position(|name| *name == joint.name)
+                .wrap_err("Failed to map joint of the serial chain (internal error)")
+        })
+        .collect()
+}
+
+/// Columns of the joints that move the link, i.e. of the joints between the root and it.
+fn link_joint_columns(chain: &k::Chain<f32>, link_name: &str) -> Result<Vec<usize>> {
+    let end = chain
+        .find_link(link_name)
+        .ok_or_else(|| RobotError::UnknownLink(link_name.to_owned()))?;
+    joint_columns(chain, &SerialChain::from_end(end))
+}
+
+/// The 6 x dof Jacobian of a link of the chain, with the linear velocity in the first
+/// three rows and the angular velocity in the last three. There is one column per movable
+/// joint of the chain, zero for the joints that do not move the link.
+fn chain_jacobian(
+    chain: &k::Chain<f32>,
+    base_pose: &Isometry<Real>,
+    link_name: &str,
+    frame: JacobianFrame,
+) -> Result<DMatrix<Real>> {
+    let end = chain
+        .find_link(link_name)
+        .ok_or_else(|| RobotError::UnknownLink(link_name.to_owned()))?;
+    let arm = SerialChain::from_end(end);
+    arm.update_transforms();
+    // relative to the chain's root, i.e. the robot's base
+    let arm_jacobian = k::jacobian(&arm);
+
+    let rotation = match frame {
+        JacobianFrame::World => base_pose.rotation,
+        JacobianFrame::Local => {
+            let link_pose = end
+                .world_transform()
+                .wrap_err("Failed to get world transform")?;
+            k_isometry_to_rapier(&link_pose).rotation.inverse()
+        }
+    };
+
+    let mut jacobian = DMatrix::zeros(6, chain.dof());
+    for (arm_column, column) in joint_columns(chain, &arm)?.into_iter().enumerate() {
+        let linear = Vector::new(
+            arm_jacobian[(0, arm_column)],
+            arm_jacobian[(1, arm_column)],
+            arm_jacobian[(2, arm_column)],
+        );
+        let angular = Vector::new(
+            arm_jacobian[(3, arm_column)],
+            arm_jacobian[(4, arm_column)],
+            arm_jacobian[(5, arm_column)],
+        );
+        jacobian
+            .fixed_view_mut::<3, 1>(0, column)
+            .copy_from(&(rotation * linear));
+        jacobian
+            .fixed_view_mut::<3, 1>(3, column)
+            .copy_from(&(rotation * angular));
+    }
+    Ok(jacobian)
+}
+
+impl Robot {
+    /// Sets the joints and returns the geometric Jacobian (6 x dof, linear rows first) of
+    /// the link's origin.
+    pub fn jacobian(
+        &mut self,
+        joints: &[f32],
+        link_name: &str,
+        frame: JacobianFrame,
+    ) -> Result<DMatrix<Real>> {
+        self.set_joints(joints)?;
+        chain_jacobian(&self.robot_chain, &self.base_pose, link_name, frame)
+    }
+
+    /// Sets the joints and returns the manipulability of the link, from the columns of the
+    /// joints between the root and the link. This does not depend on the frame of the
+    /// Jacobian.
+    pub fn manipulability(&mut self, joints: &[f32], link_name: &str) -> Result<Manipulability> {
+        let jacobian = self.jacobian(joints, link_name, JacobianFrame::World)?;
+        let columns = link_joint_columns(&self.robot_chain, link_name)?;
+        Ok(Manipulability::from_jacobian(
+            &jacobian.select_columns(&columns),
+        ))
+    }
+
+    /// Computes the Jacobians of many configurations in parallel. `configs` holds the
+    /// configurations back-to-back, `dof` values each. As with
+    /// [`Robot::link_poses_batch`], a configuration that violates the joint limits does not
+    /// fail the batch: its Jacobian is NaN. The joint positions of `self` are left
+    /// unchanged.
+    pub fn jacobian_batch(
+        &self,
+        configs: &[f32],
+        dof: usize,
+        link_name: &str,
+        frame: JacobianFrame,
+    ) -> Result<Vec<DMatrix<Real>>> {
+        ensure!(
+            dof > 0 && configs.len() % dof == 0,
+            "Configurations of size {} cannot be made of {} values",
+            dof,
+            configs.len()
+        );
+
+        let num_configs = configs.len() / dof;
+        // a few chunks per thread, so that threads finishing early can pick up more work
+        let chunk_size = (num_configs / (rayon::current_num_threads() * 4)).max(1);
+
+        let jacobians = configs
+            .par_chunks(chunk_size * dof)
+            .map_init(
+                || -> k::Chain<f32> { self.urdf_robot.clone().into() },
+                |chain, chunk| {
+                    chunk
+                        .chunks_exact(dof)
+                        .map(|joints| match set_chain_joints(chain, joints) {
+                            Ok(()) => chain_jacobian(chain, &self.base_pose, link_name, frame),
+                            Err(err)
+                                if matches!(
+                                    err.downcast_ref::<RobotError>(),
+                                    Some(RobotError::SetJointLimitViolation)
+                                ) =>
+                            {
+                                Ok(DMatrix::from_element(6, dof, Real::NAN))
+                            }
+                            Err(err) => Err(err),
+                        })
+                        .collect::<Result<Vec<_>>>()
+                },
+            )
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(jacobians.into_iter().flatten().collect())
+    }
+
+    /// Manipulability of the link (see [`Robot::manipulability`]) for many configurations,
+    /// computed in parallel. Configurations that violate the joint limits give NaN
+    /// metrics.
+    pub fn manipulability_batch(
+        &self,
+        configs: &[f32],
+        dof: usize,
+        link_name: &str,
+    ) -> Result<Vec<Manipulability>> {
+        let columns = link_joint_columns(&self.robot_chain, link_name)?;
+        Ok(self
+            .jacobian_batch(configs, dof, link_name, JacobianFrame::World)?
+            .iter()
+            .map(|jacobian| Manipulability::from_jacobian(&jacobian.select_columns(&columns)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::arm;
+
+    #[test]
+    fn matches_finite_differences_of_link_poses() {
+        let mut robot = arm();
+        robot.set_base_pose(Isometry::new(
+            Vector::new(0.1, 0.2, 0.3),
+            Vector::new(0.0, 0.0, 0.7),
+        ));
+        let q = [0.3, 0.4, 0.2];
+        let world = robot.jacobian(&q, "slider", JacobianFrame::World).unwrap();
+        let local = robot.jacobian(&q, "slider", JacobianFrame::Local).unwrap();
+        let to_local = robot.link_pose(&q, "slider").unwrap().rotation.inverse();
+
+        let h = 1e-3;
+        for joint in 0..3 {
+            let (mut q_plus, mut q_minus) = (q, q);
+            q_plus[joint] += h;
+            q_minus[joint] -= h;
+            let plus = robot.link_pose(&q_plus, "slider").unwrap();
+            let minus = robot.link_pose(&q_minus, "slider").unwrap();
+            let linear = (plus.translation.vector - minus.translation.vector) / (2.0 * h);
+            let angular = (plus.rotation * minus.rotation.inverse()).scaled_axis() / (2.0 * h);
+
+            let column = |jacobian: &DMatrix<Real>, row| {
+                Vector::new(
+                    jacobian[(row, joint)],
+                    jacobian[(row + 1, joint)],
+                    jacobian[(row + 2, joint)],
+                )
+            };
+            assert!((column(&world, 0) - linear).norm() < 5e-3, "{}", joint);
+            assert!((column(&world, 3) - angular).norm() < 5e-3, "{}", joint);
+            assert!((column(&local, 0) - to_local * linear).norm() < 5e-3);
+            assert!((column(&local, 3) - to_local * angular).norm() < 5e-3);
+        }
+    }
+
+    #[test]
+    fn manipulability_only_uses_the_joints_that_move_the_link() {
+        let mut robot = arm();
+        let q = [0.3, 0.4, 0.2];
+        // link2 is moved by joint1 and joint2 only, whose axes are orthogonal and go
+        // through its origin
+        let jacobian = robot.jacobian(&q, "link2", JacobianFrame::World).unwrap();
+        assert!(jacobian.column(2).iter().all(|value| *value == 0.0));
+        assert!(Manipulability::from_jacobian(&jacobian).manipulability < 1e-6);
+
+        let metrics = robot.manipulability(&q, "link2").unwrap();
+        assert!((metrics.manipulability - 1.0).abs() < 1e-4);
+        assert!((metrics.condition_number - 1.0).abs() < 1e-4);
+        assert!((metrics.min_singular_value - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn batch_gives_nan_for_configurations_out_of_the_limits() {
+        let mut robot = arm();
+        let configs = [0.3, 0.4, 0.2, 0.0, 0.0, 2.0];
+
+        let jacobians = robot
+            .jacobian_batch(&configs, 3, "slider", JacobianFrame::World)
+            .unwrap();
+        assert_eq!(jacobians.len(), 2);
+        assert_eq!(
+            jacobians[0],
+            robot
+                .jacobian(&configs[..3], "slider", JacobianFrame::World)
+                .unwrap()
+        );
+        assert_eq!(jacobians[1].shape(), (6, 3));
+        assert!(jacobians[1].iter().all(|value| value.is_nan()));
+
+        let metrics = robot.manipulability_batch(&configs, 3, "link2").unwrap();
+        assert_eq!(
+            metrics[0],
+            robot.manipulability(&configs[..3], "link2").unwrap()
+        );
+        assert!(metrics[1].manipulability.is_nan());
+
+        assert!(robot
+            .jacobian_batch(&configs[..5], 3, "slider", JacobianFrame::World)
+            .is_err());
+    }
+}
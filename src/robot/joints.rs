@@ -0,0 +1,247 @@
+//! Joints by name, and the mapping between the active joint vector (what
+//! [`Robot::set_joints`] takes) and the full one (which also holds the mimic joints).
+//!
+//! The active joints are the movable joints that do not mimic another joint, in the
+//! order of k's chain. Fixed joints are in neither vector.
+
+use std::collections::HashMap;
+
+use eyre::{ensure, Result};
+
+use super::{Robot, RobotError};
+
+impl Robot {
+    /// Names of the joints of [`Robot::set_joints`], in order.
+    pub fn active_joint_names(&self) -> Vec<String> {
+        self.robot_chain
+            .iter_joints()
+            .map(|joint| joint.name.clone())
+            .collect()
+    }
+
+    /// Names of every movable joint (including the mimic joints), in chain order.
+    pub fn movable_joint_names(&self) -> Vec<String> {
+        self.robot_chain
+            .iter()
+            .filter(|node| node.joint().is_movable())
+            .map(|node| node.joint().name.clone())
+            .collect()
+    }
+
+    /// Errors with [`RobotError::UnknownJoint`] if the urdf has no such joint, and with
+    /// [`RobotError::InactiveJoint`] if it is fixed or mimics another joint.
+    fn active_joint_index(&self, active_names: &[String], name: &str) -> Result<usize> {
+        if let Some(idx) = active_names.iter().position(|n| n == name) {
+            return Ok(idx);
+        }
+        if self
+            .urdf_robot
+            .joints
+            .iter()
+            .any(|joint| joint.name == name)
+        {
+            Err(RobotError::InactiveJoint(name.to_owned()).into())
+        } else {
+            Err(RobotError::UnknownJoint(name.to_owned()).into())
+        }
+    }
+
+    /// Sets the given joints, keeping the others at their current positions. Nothing is
+    /// changed if a name is unknown or not an active joint.
+    pub fn set_joints_by_name(&mut self, positions: &HashMap<String, f32>) -> Result<()> {
+        let active_names = self.active_joint_names();
+        let mut joints = self.robot_chain.joint_positions();
+        for (name, position) in positions {
+            joints[self.active_joint_index(&active_names, name)?] = *position;
+        }
+        self.set_joints(&joints)
+    }
+
+    /// Current position of every movable joint (including the mimic joints), by name.
+    pub fn joint_positions_by_name(&self) -> HashMap<String, f32> {
+        self.robot_chain
+            .iter()
+            .filter_map(|node| {
+                let joint = node.joint();
+                joint
+                    .joint_position()
+                    .map(|position| (joint.name.clone(), position))
+            })
+            .collect()
+    }
+
+    /// Full vector (in the order of [`Robot::movable_joint_names`]) of an active one, with
+    /// the mimic joints following their parent joint.
+    pub fn active_to_full(&self, active: &[f32]) -> Result<Vec<f32>> {
+        let active_names = self.active_joint_names();
+        ensure!(
+            active.len() == active_names.len(),
+            "Expected {} active joint values, got {}",
+            active_names.len(),
+            active.len()
+        );
+        let positions: HashMap<&str, f32> = active_names
+            .iter()
+            .map(String::as_str)
+            .zip(active.iter().copied())
+            .collect();
+
+        self.movable_joint_names()
+            .iter()
+            .map(|name| {
+                if let Some(position) = positions.get(name.as_str()) {
+                    return Ok(*position);
+                }
+                let mimic = self
+                    .urdf_robot
+                    .joints
+                    .iter()
+                    .find(|joint| joint.name == *name)
+                    .and_then(|joint| joint.mimic.as_ref())
+                    .ok_or_else(|| RobotError::UnknownJoint(name.clone()))?;
+                let parent = positions
+                    .get(mimic.joint.as_str())
+                    .ok_or_else(|| RobotError::InactiveJoint(mimic.joint.clone()))?;
+                Ok(parent * mimic.multiplier.unwrap_or(1.0) as f32
+                    + mimic.offset.unwrap_or(0.0) as f32)
+            })
+            .collect()
+    }
+
+    /// Active vector (in the order of [`Robot::active_joint_names`]) of a full one,
+    /// dropping the mimic joints.
+    pub fn full_to_active(&self, full: &[f32]) -> Result<Vec<f32>> {
+        let movable_names = self.movable_joint_names();
+        ensure!(
+            full.len() == movable_names.len(),
+            "Expected {} joint values, got {}",
+            movable_names.len(),
+            full.len()
+        );
+        let active_names = self.active_joint_names();
+        Ok(movable_names
+            .iter()
+            .zip(full)
+            .filter(|(name, _)| active_names.contains(name))
+            .map(|(_, position)| *position)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URDF: &str = r#"<robot name="gripper">
+  <link name="base" />
+  <link name="link1" />
+  <link name="finger_left" />
+  <link name="finger_right" />
+  <link name="tool" />
+  <joint name="joint1" type="revolute">
+    <parent link="base" />
+    <child link="link1" />
+    <axis xyz="0 0 1" />
+    <limit lower="-1" upper="1" effort="0" velocity="0" />
+  </joint>
+  <joint name="finger_left_joint" type="prismatic">
+    <parent link="link1" />
+    <child link="finger_left" />
+    <axis xyz="0 1 0" />
+    <limit lower="0" upper="0.04" effort="0" velocity="0" />
+  </joint>
+  <joint name="finger_right_joint" type="prismatic">
+    <parent link="link1" />
+    <child link="finger_right" />
+    <axis xyz="0 -1 0" />
+    <limit lower="-0.1" upper="0.1" effort="0" velocity="0" />
+    <mimic joint="finger_left_joint" multiplier="2" offset="0.01" />
+  </joint>
+  <joint name="tool_joint" type="fixed">
+    <parent link="link1" />
+    <child link="tool" />
+  </joint>
+</robot>"#;
+
+    fn robot() -> Robot {
+        Robot::from_urdf_robot(urdf_rs::read_from_string(URDF).unwrap(), None).unwrap()
+    }
+
+    fn position(full: &[f32], names: &[String], name: &str) -> f32 {
+        full[names.iter().position(|n| n == name).unwrap()]
+    }
+
+    #[test]
+    fn active_joints_leave_out_mimic_and_fixed_joints() {
+        let robot = robot();
+        let mut active = robot.active_joint_names();
+        active.sort();
+        assert_eq!(active, ["finger_left_joint", "joint1"]);
+
+        let mut movable = robot.movable_joint_names();
+        movable.sort();
+        assert_eq!(
+            movable,
+            ["finger_left_joint", "finger_right_joint", "joint1"]
+        );
+    }
+
+    #[test]
+    fn mimic_joints_follow_their_parent() {
+        let robot = robot();
+        let active_names = robot.active_joint_names();
+        let movable_names = robot.movable_joint_names();
+        let active: Vec<f32> = active_names
+            .iter()
+            .map(|name| if name == "joint1" { 0.5 } else { 0.02 })
+            .collect();
+
+        let full = robot.active_to_full(&active).unwrap();
+        assert_eq!(full.len(), 3);
+        assert_eq!(position(&full, &movable_names, "joint1"), 0.5);
+        assert_eq!(position(&full, &movable_names, "finger_left_joint"), 0.02);
+        assert!((position(&full, &movable_names, "finger_right_joint") - 0.05).abs() < 1e-6);
+
+        assert_eq!(robot.full_to_active(&full).unwrap(), active);
+        assert!(robot.active_to_full(&full).is_err());
+        assert!(robot.full_to_active(&active).is_err());
+    }
+
+    #[test]
+    fn sets_joints_by_name() {
+        let mut robot = robot();
+        robot
+            .set_joints_by_name(&HashMap::from([("finger_left_joint".to_owned(), 0.02)]))
+            .unwrap();
+        let positions = robot.joint_positions_by_name();
+        assert_eq!(positions["finger_left_joint"], 0.02);
+        assert_eq!(positions["joint1"], 0.0);
+        assert!((positions["finger_right_joint"] - 0.05).abs() < 1e-6);
+
+        for (name, inactive) in [
+            ("finger_right_joint", true),
+            ("tool_joint", true),
+            ("no_such_joint", false),
+        ] {
+            let err = robot
+                .set_joints_by_name(&HashMap::from([
+                    ("joint1".to_owned(), 0.5),
+                    (name.to_owned(), 0.0),
+                ]))
+                .unwrap_err();
+            if inactive {
+                assert!(matches!(
+                    err.downcast_ref::<RobotError>(),
+                    Some(RobotError::InactiveJoint(n)) if n == name
+                ));
+            } else {
+                assert!(matches!(
+                    err.downcast_ref::<RobotError>(),
+                    Some(RobotError::UnknownJoint(n)) if n == name
+                ));
+            }
+            // nothing is changed on error
+            assert_eq!(robot.joint_positions_by_name()["joint1"], 0.0);
+        }
+    }
+}
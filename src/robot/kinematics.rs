@@ -0,0 +1,188 @@
+//! Forward kinematics: the world poses of the links for given joint positions.
+
+use eyre::{ensure, ContextCompat, Result};
+use rapier3d::math::{Isometry, Real, Vector};
+use rapier3d::na::{Quaternion, UnitQuaternion};
+use rayon::prelude::*;
+
+use super::{k_isometry_to_rapier, set_chain_joints, Robot, RobotError};
+
+/// World poses of every link of the chain, in chain order, with the robot placed at
+/// `base_pose`.
+fn chain_link_poses(
+    chain: &k::Chain<f32>,
+    base_pose: &Isometry<Real>,
+) -> Result<Vec<Isometry<Real>>> {
+    chain.update_transforms();
+    chain
+        .iter()
+        .map(|node| {
+            let transform = node
+                .world_transform()
+                .wrap_err("Failed to get world transform")?;
+            Ok(base_pose * k_isometry_to_rapier(&transform))
+        })
+        .collect()
+}
+
+/// The pose of every link of a configuration that violates the joint limits.
+fn nan_pose() -> Isometry<Real> {
+    Isometry::from_parts(
+        Vector::repeat(Real::NAN).into(),
+        UnitQuaternion::new_unchecked(Quaternion::new(Real::NAN, Real::NAN, Real::NAN, Real::NAN)),
+    )
+}
+
+impl Robot {
+    /// Names of the links, in the order of [`Robot::link_poses`].
+    pub fn link_names(&self) -> Vec<String> {
+        self.robot_chain
+            .iter()
+            .filter_map(|node| self.joint_link_map.get(&node.joint().name).cloned())
+            .collect()
+    }
+
+    /// Sets the joints and returns the world pose of every link, in the order of
+    /// [`Robot::link_names`]. The poses include the robot's base pose.
+    pub fn link_poses(&mut self, joints: &[f32]) -> Result<Vec<Isometry<Real>>> {
+        self.set_joints(joints)?;
+        chain_link_poses(&self.robot_chain, &self.base_pose)
+    }
+
+    /// Sets the joints and returns the world pose of a single link.
+    pub fn link_pose(&mut self, joints: &[f32], link_name: &str) -> Result<Isometry<Real>> {
+        let idx = self
+            .link_names()
+            .iter()
+            .position(|name| name == link_name)
+            .ok_or_else(|| RobotError::UnknownLink(link_name.to_owned()))?;
+        Ok(self.link_poses(joints)?[idx])
+    }
+
+    /// Computes the link poses of many configurations in parallel. `configs` holds the
+    /// configurations back-to-back, `dof` values each, and the result holds the
+    /// [`Robot::link_poses`] of every configuration back-to-back. As with
+    /// [`Robot::has_collision_batch`], a configuration that violates the joint limits does
+    /// not fail the batch: all its poses are NaN. The joint positions of `self` are left
+    /// unchanged.
+    pub fn link_poses_batch(&self, configs: &[f32], dof: usize) -> Result<Vec<Isometry<Real>>> {
+        ensure!(
+            dof > 0 && configs.len() % dof == 0,
+            "Configurations of size {} cannot be made of {} values",
+            dof,
+            configs.len()
+        );
+
+        let num_configs = configs.len() / dof;
+        let num_links = self.robot_chain.iter().count();
+        // a few chunks per thread, so that threads finishing early can pick up more work
+        let chunk_size = (num_configs / (rayon::current_num_threads() * 4)).max(1);
+
+        let poses = configs
+            .par_chunks(chunk_size * dof)
+            .map_init(
+                // only the chain is needed, which is much cheaper to build than a robot
+                || -> k::Chain<f32> { self.urdf_robot.clone().into() },
+                |chain, chunk| {
+                    let mut poses = Vec::new();
+                    for joints in chunk.chunks_exact(dof) {
+                        match set_chain_joints(chain, joints) {
+                            Ok(()) => poses.extend(chain_link_poses(chain, &self.base_pose)?),
+                            Err(err)
+                                if matches!(
+                                    err.downcast_ref::<RobotError>(),
+                                    Some(RobotError::SetJointLimitViolation)
+                                ) =>
+                            {
+                                poses.extend(std::iter::repeat(nan_pose()).take(num_links))
+                            }
+                            Err(err) => return Err(err),
+                        }
+                    }
+                    Ok(poses)
+                },
+            )
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(poses.into_iter().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::{arm, slider_position};
+    use rapier3d::na::Vector4;
+
+    #[test]
+    fn link_poses_match_the_chain() {
+        let mut robot = arm();
+        let q = [0.7, 0.3, 0.4];
+        let poses = robot.link_poses(&q).unwrap();
+        assert_eq!(robot.link_names(), ["base", "link1", "link2", "slider"]);
+
+        for (node, pose) in robot.robot_chain.iter().zip(&poses) {
+            let transform = node.world_transform().unwrap();
+            let (t, r) = (transform.translation.vector, transform.rotation.coords);
+            assert!((pose.translation.vector - Vector::new(t.x, t.y, t.z)).norm() < 1e-5);
+            let dot = pose.rotation.coords.dot(&Vector4::new(r.x, r.y, r.z, r.w));
+            assert!(dot.abs() > 1.0 - 1e-5);
+        }
+        let slider = Vector::from(slider_position(&q));
+        assert!((poses[3].translation.vector - slider).norm() < 1e-5);
+        assert_eq!(robot.link_pose(&q, "slider").unwrap(), poses[3]);
+
+        let base_pose = Isometry::new(Vector::new(1.0, 2.0, 3.0), Vector::new(0.0, 0.0, 0.5));
+        robot.set_base_pose(base_pose);
+        let pose = robot.link_pose(&q, "slider").unwrap();
+        assert!(
+            (pose.translation.vector - (base_pose * poses[3]).translation.vector).norm() < 1e-5
+        );
+
+        let err = robot.link_pose(&q, "missing").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RobotError>(),
+            Some(RobotError::UnknownLink(_))
+        ));
+    }
+
+    #[test]
+    fn batch_matches_single_configurations() {
+        let mut robot = arm();
+        robot.set_joints(&[0.1, 0.2, 0.3]).unwrap();
+        let configs = [0.7, 0.3, 0.4, 0.0, 0.0, 0.0, -0.5, 1.0, 0.9];
+
+        let poses = robot.link_poses_batch(&configs, 3).unwrap();
+        assert_eq!(poses.len(), 3 * 4);
+        assert_eq!(robot.robot_chain.joint_positions(), [0.1, 0.2, 0.3]);
+        for (joints, poses) in configs.chunks(3).zip(poses.chunks(4)) {
+            assert_eq!(poses, robot.link_poses(joints).unwrap());
+        }
+    }
+
+    #[test]
+    fn batch_gives_nan_poses_for_configurations_out_of_the_limits() {
+        let robot = arm();
+        let poses = robot
+            .link_poses_batch(&[0.0, 0.0, 0.0, 0.0, 2.0, 0.0], 3)
+            .unwrap();
+        assert_eq!(poses.len(), 2 * 4);
+        assert!(poses[..4].iter().all(|pose| pose
+            .translation
+            .vector
+            .iter()
+            .all(|v| v.is_finite())));
+        assert!(poses[4..].iter().all(|pose| {
+            pose.translation.vector.iter().all(|v| v.is_nan())
+                && pose.rotation.coords.iter().all(|v| v.is_nan())
+        }));
+    }
+
+    #[test]
+    fn batch_rejects_partial_configurations() {
+        let robot = arm();
+        assert!(robot.link_poses_batch(&[0.0; 4], 3).is_err());
+        assert!(robot.link_poses_batch(&[0.0; 3], 0).is_err());
+        assert!(robot.link_poses_batch(&[], 3).unwrap().is_empty());
+    }
+}
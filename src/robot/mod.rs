@@ -1,25 +1,65 @@
 use bevy_rapier3d::rapier::prelude::RigidBody;
 // use k::nalgebra::Isometry;
-use log::debug;
-use std::collections::HashMap;
+use log::{debug, warn};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
 use std::path::Path;
+use std::str::FromStr;
 
 use crate::collision_checker::{
-    group_flag_from_idx, ColliderBuilderActivateRobotLinkCollision, SimpleCollisionPipeline,
+    ColliderBuilderActivateRobotLinkCollision, ColliderOwner, SimpleCollisionPipeline,
 };
 use crate::util::replace_package_with_base_dir;
 use eyre::{Context, ContextCompat, OptionExt, Result};
+pub use rapier3d::math::Isometry;
 use rapier3d::math::Real;
 pub use rapier3d::prelude::ColliderHandle;
 use rapier3d::{
-    math::{Isometry, Point, Vector},
+    math::{Point, Vector},
     na::{self},
-    prelude::{ColliderBuilder, MeshConverter, SharedShape, TriMeshFlags},
+    prelude::{Collider, ColliderBuilder, MeshConverter, SharedShape, TriMeshFlags},
 };
 use urdf_rs::{self, Geometry, Pose};
 
-
+pub mod attached;
+pub mod base;
+pub mod batch;
+pub mod collision_matrix;
+pub mod collision_report;
+pub mod consistency;
+pub mod distance;
+pub mod ik;
+pub mod jacobian;
+pub mod joints;
+pub mod kinematics;
+pub mod motion;
+pub mod obstacle;
+pub mod padding;
 pub mod plugin;
+pub mod point_cloud;
+pub mod raycast;
+pub mod scene;
+pub mod shape_cache;
+pub mod srdf;
+#[cfg(test)]
+mod test_util;
+pub mod voxel_grid;
+
+pub use attached::AttachedObject;
+pub use base::BaseJoint;
+pub use collision_report::{CollisionReport, ContactPoint, LinkContact};
+pub use consistency::GeometryMismatch;
+pub use distance::{DistanceReport, PairDistance};
+pub use ik::{IkOption, IkTarget};
+pub use jacobian::{JacobianFrame, Manipulability};
+pub use obstacle::Obstacle;
+pub use point_cloud::PointCloudOption;
+pub use raycast::RayHit;
+pub use scene::{RobotScene, SceneCollisionResult};
+pub use shape_cache::clear_mesh_shape_cache;
+pub use srdf::Srdf;
+pub use voxel_grid::VoxelGrid;
 
 pub struct Robot {
     // links: Vec<Link>,
@@ -27,11 +67,76 @@ pub struct Robot {
     pub collision_checker: SimpleCollisionPipeline,
     pub robot_chain: k::Chain<f32>,
     pub urdf_robot: urdf_rs::Robot,
+    /// pose of the robot's root link in the world
+    pub(crate) base_pose: Isometry<Real>,
     pub colliders: HashMap<String, Vec<ColliderHandle>>,
+    /// pose of each link collider relative to its link (i.e. the collision origin)
+    pub collider_offsets: HashMap<ColliderHandle, Isometry<Real>>,
+    /// directory that relative (and `package://`) mesh filenames are resolved against
+    pub base_dir: Option<String>,
     pub joint_link_map: HashMap<String, String>,
+    pub obstacles: HashMap<String, Obstacle>,
+    pub attached_objects: HashMap<String, AttachedObject>,
+    next_attached_object_id: usize,
+    /// semantic description (planning groups, named states, ...); empty if no srdf was given
+    pub srdf: Srdf,
+    /// padding applied to every link without its own padding (see [`Robot::set_padding`])
+    pub(crate) padding: Real,
+    pub(crate) link_padding: HashMap<String, Real>,
+}
+
+/// Sets the joint positions of a chain, reporting limit violations as
+/// [`RobotError::SetJointLimitViolation`].
+pub(crate) fn set_chain_joints(chain: &k::Chain<f32>, joints: &[f32]) -> Result<()> {
+    let result = chain.set_joint_positions(joints);
+
+    // this error is mapped to collided result
+    if let Err(k::Error::OutOfLimitError {
+        joint_name: _,
+        position: _,
+        max_limit: _,
+        min_limit: _,
+    }) = &result
+    {
+        debug!("{:#?}", &result);
+        return Err(RobotError::SetJointLimitViolation.into());
+    }
+
+    // if there's error in setting joint positions, return error
+    if let Err(e) = result {
+        return Err(RobotError::FailedToSetJointPositions(e).into());
+    }
+
+    Ok(())
+}
+
+impl Clone for Robot {
+    /// Clones the robot, sharing the (immutable) collision shapes with the original.
+    fn clone(&self) -> Self {
+        // k's chain nodes are reference counted, so the chain has to be rebuilt
+        let robot_chain: k::Chain<f32> = self.urdf_robot.clone().into();
+        robot_chain.set_joint_positions_unchecked(&self.robot_chain.joint_positions());
+
+        Self {
+            collision_checker: self.collision_checker.clone(),
+            robot_chain,
+            urdf_robot: self.urdf_robot.clone(),
+            base_pose: self.base_pose,
+            colliders: self.colliders.clone(),
+            collider_offsets: self.collider_offsets.clone(),
+            base_dir: self.base_dir.clone(),
+            joint_link_map: self.joint_link_map.clone(),
+            obstacles: self.obstacles.clone(),
+            attached_objects: self.attached_objects.clone(),
+            next_attached_object_id: self.next_attached_object_id,
+            srdf: self.srdf.clone(),
+            padding: self.padding,
+            link_padding: self.link_padding.clone(),
+        }
+    }
 }
 
-fn pose_to_isometry(pose: &Pose) -> Isometry<Real> {
+pub fn pose_to_isometry(pose: &Pose) -> Isometry<Real> {
     Isometry::from_parts(
         Point::new(
             pose.xyz[0] as Real,
@@ -63,15 +168,27 @@ fn k_isometry_to_rapier(isometry: &k::Isometry3<f32>) -> Isometry<Real> {
     )
 }
 
+fn rapier_isometry_to_k(isometry: &Isometry<Real>) -> k::Isometry3<f32> {
+    let (roll, pitch, yaw) = isometry.rotation.euler_angles();
+    k::Isometry3::from_parts(
+        k::nalgebra::Translation3::new(
+            isometry.translation.vector.x,
+            isometry.translation.vector.y,
+            isometry.translation.vector.z,
+        ),
+        k::nalgebra::UnitQuaternion::from_euler_angles(roll, pitch, yaw),
+    )
+}
+
 pub fn geometry_to_colliders(
     mesh_dir: &Option<&str>,
     geometry: &Geometry,
     origin: &Pose,
+    mesh_mode: MeshColliderMode,
 ) -> Vec<ColliderBuilder> {
     let mut shape_transform = Isometry::identity();
 
     let collider_blueprint = ColliderBuilder::default().density(0.0);
-    let trimesh_flags = TriMeshFlags::all();
 
     let mut colliders = Vec::new();
     match &geometry {
@@ -95,28 +212,20 @@ pub fn geometry_to_colliders(
             colliders.push(SharedShape::ball(*radius as Real));
         }
         Geometry::Mesh { filename, scale } => {
-            let _scale = scale
+            let scale = scale
                 .map(|s| Vector::new(s[0] as Real, s[1] as Real, s[2] as Real))
                 .unwrap_or_else(|| Vector::<Real>::repeat(1.0));
-
-            let loader = mesh_loader::Loader::default();
             let full_path = replace_package_with_base_dir(filename, mesh_dir);
-            if let Ok(scene) = loader.load(full_path) {
-                for (raw_mesh, _) in scene.meshes.into_iter().zip(scene.materials) {
-                    let vertices: Vec<_> = raw_mesh
-                        .vertices
-                        .iter()
-                        .map(|xyz| Point::new(xyz[0], xyz[1], xyz[2]))
-                        .collect();
-                    let indices: Vec<_> = raw_mesh.faces;
-                    let converter = MeshConverter::TriMeshWithFlags(trimesh_flags);
-                    if let Ok((shape, _)) = converter.convert(vertices, indices) {
-                        colliders.push(shape)
-                    }
-                }
-            }
+            colliders.extend(shape_cache::mesh_shapes(&full_path, scale, mesh_mode));
+        }
+        Geometry::Capsule { radius, length } => {
+            // urdf capsules are along the Z axis, with `length` the distance between the
+            // centers of the two end caps
+            colliders.push(SharedShape::capsule_z(
+                *length as Real / 2.0,
+                *radius as Real,
+            ));
         }
-        Geometry::Capsule { radius, length } => todo!(),
     };
 
     colliders
@@ -139,17 +248,102 @@ pub enum RobotError {
 
     #[error("Failed to set joint positions: Joint limit out of bound")]
     SetJointLimitViolation,
+
+    #[error("No obstacle named '{0}'")]
+    UnknownObstacle(String),
+
+    #[error("No link named '{0}'")]
+    UnknownLink(String),
+
+    #[error("No planning group named '{0}'")]
+    UnknownPlanningGroup(String),
+
+    #[error("No group state '{name}' for planning group '{group}'")]
+    UnknownGroupState { group: String, name: String },
+
+    #[error("Planning group '{0}' contains itself through its subgroups")]
+    CyclicPlanningGroup(String),
+
+    #[error("tip_link {tip_link} is not a descendant of base_link {base_link}")]
+    NotADescendant { tip_link: String, base_link: String },
+
+    #[error("No attached object named '{0}'")]
+    UnknownAttachedObject(String),
+
+    #[error("No robot named '{0}' in the scene")]
+    UnknownRobot(String),
+
+    #[error("No joint named '{0}'")]
+    UnknownJoint(String),
+
+    #[error("Joint '{0}' is not an active joint (it is fixed or mimics another joint)")]
+    InactiveJoint(String),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How collision meshes are turned into collision shapes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MeshColliderMode {
+    /// The triangle mesh as-is. Exact, but slow to check, and only the surface is solid.
+    #[default]
+    TriMesh,
+    /// The convex hull of the mesh.
+    ConvexHull,
+    /// A (V-HACD) decomposition of the mesh into convex parts.
+    ConvexDecomposition,
+}
+
+impl MeshColliderMode {
+    pub fn converter(&self) -> MeshConverter {
+        match self {
+            MeshColliderMode::TriMesh => MeshConverter::TriMeshWithFlags(TriMeshFlags::all()),
+            MeshColliderMode::ConvexHull => MeshConverter::ConvexHull,
+            MeshColliderMode::ConvexDecomposition => MeshConverter::ConvexDecomposition,
+        }
+    }
+}
+
+impl FromStr for MeshColliderMode {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "trimesh" => Ok(MeshColliderMode::TriMesh),
+            "convex_hull" => Ok(MeshColliderMode::ConvexHull),
+            "convex_decomposition" => Ok(MeshColliderMode::ConvexDecomposition),
+            _ => Err(eyre::eyre!(
+                "Unknown mesh collider mode '{}' (expected one of 'trimesh', 'convex_hull' or 'convex_decomposition')",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UrdfRobotOption {
     pub collision_exclude_neighbour: bool,
+    pub mesh_collider_mode: MeshColliderMode,
+    /// per-link overrides of `mesh_collider_mode`, by link name
+    pub link_mesh_collider_modes: HashMap<String, MeshColliderMode>,
+    /// virtual joints inserted above the root link, at the start of the joint vector
+    pub base_joint: BaseJoint,
+}
+
+impl UrdfRobotOption {
+    pub fn mesh_collider_mode_of(&self, link_name: &str) -> MeshColliderMode {
+        self.link_mesh_collider_modes
+            .get(link_name)
+            .copied()
+            .unwrap_or(self.mesh_collider_mode)
+    }
 }
 
 impl Default for UrdfRobotOption {
     fn default() -> Self {
         Self {
             collision_exclude_neighbour: true,
+            mesh_collider_mode: MeshColliderMode::default(),
+            link_mesh_collider_modes: HashMap::new(),
+            base_joint: BaseJoint::default(),
         }
     }
 }
@@ -157,15 +351,27 @@ impl Default for UrdfRobotOption {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CollisionResult {
     Free,
-    Collision,
+    /// Two links of the robot are in contact.
+    SelfCollision,
+    /// A link of the robot is in contact with an environment obstacle.
+    EnvironmentCollision,
     JointLimitViolation,
 }
 
+/// Whether an interaction is between two links of the robot, or between a link and
+/// an environment obstacle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContactKind {
+    SelfCollision,
+    Environment,
+}
+
 impl From<CollisionResult> for bool {
     fn from(val: CollisionResult) -> Self {
         match val {
             CollisionResult::Free => false,
-            CollisionResult::Collision => true,
+            CollisionResult::SelfCollision => true,
+            CollisionResult::EnvironmentCollision => true,
             CollisionResult::JointLimitViolation => true,
         }
     }
@@ -176,22 +382,46 @@ impl Robot {
         self.urdf_robot.name.as_str()
     }
 
-    pub fn from_file(urdf_path: &str) -> Result<Self> {
+    pub fn from_file(urdf_path: &str, srdf_path: Option<&str>) -> Result<Self> {
+        Self::from_file_with_option(urdf_path, srdf_path, &UrdfRobotOption::default())
+    }
+
+    pub fn from_file_with_option(
+        urdf_path: &str,
+        srdf_path: Option<&str>,
+        option: &UrdfRobotOption,
+    ) -> Result<Self> {
         let path = Path::new(urdf_path);
         let urdf_robot: urdf_rs::Robot = urdf_rs::read_file(path)?;
-        Self::from_urdf_robot(urdf_robot, path.parent().and_then(|p| p.to_str()))
+        let mut robot = Self::from_urdf_robot_with_option(
+            urdf_robot,
+            path.parent().and_then(|p| p.to_str()),
+            option,
+        )?;
+
+        if let Some(srdf_path) = srdf_path {
+            robot.apply_srdf(srdf::read_file(srdf_path)?);
+        }
+        Ok(robot)
     }
 
     pub fn from_urdf_robot(urdf_robot: urdf_rs::Robot, base_dir: Option<&str>) -> Result<Self> {
+        Self::from_urdf_robot_with_option(urdf_robot, base_dir, &UrdfRobotOption::default())
+    }
+
+    pub fn from_urdf_robot_with_option(
+        mut urdf_robot: urdf_rs::Robot,
+        base_dir: Option<&str>,
+        option: &UrdfRobotOption,
+    ) -> Result<Self> {
+        base::add_virtual_base_joints(&mut urdf_robot, option.base_joint)?;
+
         let mut colliders_mappings = HashMap::new();
+        let mut collider_offsets = HashMap::new();
 
         let mut collision_checker = SimpleCollisionPipeline::default();
 
-        let option = UrdfRobotOption::default();
-
-        let mut mapping_parent_to_child = HashMap::new();
-        let mut mapping_child_to_parent = HashMap::new();
-        // build a mappint to mapping if we want to exclude neighbour collision
+        // exclude collisions between links that are connected by a joint
         if option.collision_exclude_neighbour {
             let mut link_name_to_idx = HashMap::new();
             // build a mapping from link_name to link_idx
@@ -200,14 +430,10 @@ impl Robot {
             }
 
             for joint in urdf_robot.joints.iter() {
-                mapping_child_to_parent.insert(
-                    joint.child.link.as_str(),
+                collision_checker.allowed_collisions.allow(
                     *link_name_to_idx
                         .get(joint.parent.link.as_str())
                         .expect("internal logic error: failed to map link name to index"),
-                );
-                mapping_parent_to_child.insert(
-                    joint.parent.link.as_str(),
                     *link_name_to_idx
                         .get(joint.child.link.as_str())
                         .expect("internal logic error: failed to map link name to index"),
@@ -215,38 +441,23 @@ impl Robot {
             }
         }
 
-        use rapier3d::prelude::Group;
-
         for (link_idx, link) in urdf_robot.links.iter().enumerate() {
-            let mut exclude_group = Group::empty();
-
-            if option.collision_exclude_neighbour {
-                let name = link.name.as_str();
-                if let Some(child) = mapping_parent_to_child.get(name) {
-                    exclude_group |= group_flag_from_idx(*child);
-                }
-                if let Some(parent) = mapping_child_to_parent.get(name) {
-                    exclude_group |= group_flag_from_idx(*parent);
-                }
-            }
-
             let mut collider_handles = Vec::new();
+            let mesh_mode = option.mesh_collider_mode_of(&link.name);
             for collision in &link.collision {
-                let mut colliders: Vec<_> =
-                    geometry_to_colliders(&base_dir, &collision.geometry, &collision.origin)
-                        .drain(..)
-                        .map(|collider| {
-                            collider
-                                .activate_as_robot_link_with_exclude_group(link_idx, exclude_group)
-                                .build()
-                        })
-                        .collect();
-
-                collider_handles.extend(
-                    colliders
-                        .drain(..)
-                        .map(|c| collision_checker.collider_set.insert(c)),
-                );
+                for collider in geometry_to_colliders(
+                    &base_dir,
+                    &collision.geometry,
+                    &collision.origin,
+                    mesh_mode,
+                ) {
+                    let offset = collider.position;
+                    let handle = collision_checker
+                        .collider_set
+                        .insert(collider.activate_as_robot_link(link_idx).build());
+                    collider_offsets.insert(handle, offset);
+                    collider_handles.push(handle);
+                }
             }
             colliders_mappings.insert(link.name.clone(), collider_handles);
         }
@@ -255,51 +466,176 @@ impl Robot {
             joint_link_map: k::urdf::joint_to_link_map(&urdf_robot),
             robot_chain: urdf_robot.clone().into(),
             urdf_robot,
+            base_pose: Isometry::identity(),
             colliders: colliders_mappings,
+            collider_offsets,
+            base_dir: base_dir.map(str::to_owned),
             collision_checker,
+            obstacles: HashMap::new(),
+            attached_objects: HashMap::new(),
+            next_attached_object_id: 0,
+            srdf: Srdf::default(),
+            padding: 0.0,
+            link_padding: HashMap::new(),
         })
     }
 
-    pub fn set_joints(&mut self, joints: &[f32]) -> Result<()> {
-        let result = self.robot_chain.set_joint_positions(joints);
-
-        // this error is mapped to collided result
-        if let Err(k::Error::OutOfLimitError {
-            joint_name: _,
-            position: _,
-            max_limit: _,
-            min_limit: _,
-        }) = &result
-        {
-            debug!("{:#?}", &result);
-            return Err(RobotError::SetJointLimitViolation.into());
+    pub fn base_pose(&self) -> Isometry<Real> {
+        self.base_pose
+    }
+
+    /// Places the robot's root link (or the origin of its virtual base joints, see
+    /// [`UrdfRobotOption::base_joint`]) in the world. Link colliders follow on the next
+    /// check.
+    pub fn set_base_pose(&mut self, pose: Isometry<Real>) {
+        self.base_pose = pose;
+    }
+
+    pub fn dof(&self) -> usize {
+        self.robot_chain.dof()
+    }
+
+    /// Index of the link within the urdf, which is also its index in the collision matrix.
+    pub fn link_index(&self, link_name: &str) -> Result<usize> {
+        self.urdf_robot
+            .links
+            .iter()
+            .position(|link| link.name == link_name)
+            .ok_or_else(|| RobotError::UnknownLink(link_name.to_owned()).into())
+    }
+
+    /// Excludes the srdf's `<disable_collisions>` pairs from self-collision checking,
+    /// and keeps the srdf around for querying groups, group states and end effectors.
+    pub fn apply_srdf(&mut self, srdf: Srdf) {
+        self.apply_disabled_collisions(&srdf.disable_collisions);
+        self.srdf = srdf;
+    }
+
+    /// Excludes the given link pairs from self-collision checking.
+    pub fn apply_disabled_collisions(&mut self, pairs: &[srdf::DisableCollisions]) {
+        for pair in pairs {
+            match (self.link_index(&pair.link1), self.link_index(&pair.link2)) {
+                (Ok(a), Ok(b)) => self.collision_checker.allowed_collisions.allow(a, b),
+                _ => warn!(
+                    "Ignoring disabled collision pair ({}, {}) with unknown link",
+                    pair.link1, pair.link2
+                ),
+            }
         }
+    }
 
-        // if there's error in setting joint positions, return error
-        if let Err(e) = result {
-            return Err(RobotError::FailedToSetJointPositions(e).into());
+    /// Enables or disables every collision of a link (e.g. the fingers while approaching
+    /// a grasp). This takes effect on the next collision check.
+    pub fn set_link_collision_enabled(&mut self, link_name: &str, enabled: bool) -> Result<()> {
+        let link = self.link_index(link_name)?;
+        self.collision_checker
+            .allowed_collisions
+            .set_link_enabled(link, enabled);
+        Ok(())
+    }
+
+    pub fn is_link_collision_enabled(&self, link_name: &str) -> Result<bool> {
+        let link = self.link_index(link_name)?;
+        Ok(self
+            .collision_checker
+            .allowed_collisions
+            .is_link_enabled(link))
+    }
+
+    /// Ignores collisions between the two links, from the next collision check on.
+    pub fn allow_collision(&mut self, link_a: &str, link_b: &str) -> Result<()> {
+        let (a, b) = (self.link_index(link_a)?, self.link_index(link_b)?);
+        self.collision_checker.allowed_collisions.allow(a, b);
+        Ok(())
+    }
+
+    /// Checks collisions between the two links again, e.g. after
+    /// [`Robot::allow_collision`] or for a pair disabled by the srdf.
+    pub fn disallow_collision(&mut self, link_a: &str, link_b: &str) -> Result<()> {
+        let (a, b) = (self.link_index(link_a)?, self.link_index(link_b)?);
+        self.collision_checker.allowed_collisions.disallow(a, b);
+        Ok(())
+    }
+
+    /// Resolves a planning group (its chains, joints, links and subgroups) into the
+    /// names of the joints it contains.
+    pub fn group_joint_names(&self, group_name: &str) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        self.collect_group_joint_names(group_name, &mut HashSet::new(), &mut names)?;
+
+        let mut seen = HashSet::new();
+        names.retain(|name| seen.insert(name.clone()));
+        Ok(names)
+    }
+
+    /// Appends the joints of a group to `names`. `resolving` holds the groups whose
+    /// subgroups are being resolved, so that a group containing itself is an error rather
+    /// than an endless recursion.
+    fn collect_group_joint_names(
+        &self,
+        group_name: &str,
+        resolving: &mut HashSet<String>,
+        names: &mut Vec<String>,
+    ) -> Result<()> {
+        let group = self
+            .srdf
+            .group(group_name)
+            .ok_or_else(|| RobotError::UnknownPlanningGroup(group_name.to_owned()))?;
+
+        let parent_joint_of = |link_name: &str| {
+            self.urdf_robot
+                .joints
+                .iter()
+                .find(|joint| joint.child.link == link_name)
+        };
+
+        for chain in &group.chains {
+            let mut chain_joints = Vec::new();
+            let mut link_name = chain.tip_link.as_str();
+            while link_name != chain.base_link {
+                let Some(joint) = parent_joint_of(link_name) else {
+                    // reached the root without meeting the base link
+                    return Err(if self.link_index(link_name).is_ok() {
+                        RobotError::NotADescendant {
+                            tip_link: chain.tip_link.clone(),
+                            base_link: chain.base_link.clone(),
+                        }
+                    } else {
+                        RobotError::UnknownLink(link_name.to_owned())
+                    }
+                    .into());
+                };
+                chain_joints.push(joint.name.clone());
+                link_name = joint.parent.link.as_str();
+            }
+            names.extend(chain_joints.into_iter().rev());
+        }
+        names.extend(group.joints.iter().map(|joint| joint.name.clone()));
+        for link in &group.links {
+            if let Some(joint) = parent_joint_of(&link.name) {
+                names.push(joint.name.clone());
+            }
         }
 
+        if !resolving.insert(group_name.to_owned()) {
+            return Err(RobotError::CyclicPlanningGroup(group_name.to_owned()).into());
+        }
+        for subgroup in &group.subgroups {
+            self.collect_group_joint_names(&subgroup.name, resolving, names)?;
+        }
+        resolving.remove(group_name);
         Ok(())
     }
 
-    pub fn has_collision(&mut self) -> Result<CollisionResult> {
-        // .map_err(|e| match e {
-        //         k::Error::OutOfLimitError { joint_name, position, max_limit, min_limit } => return CollisionResult::OutOfJointLimit,
-        //         // // k::Error::SetToFixedError { joint_name } => todo!(),
-        //         // k::Error::SizeMismatchError { input, required } => todo!(),
-        //         // k::Error::MimicError { from, to } => todo!(),
-        //         // k::Error::NotConvergedError { num_tried, position_diff, rotation_diff } => todo!(),
-        //         // k::Error::InverseMatrixError => todo!(),
-        //         // k::Error::PreconditionError { dof, necessary_dof } => todo!(),
-        //         // k::Error::InvalidJointNameError { joint_name } => todo!(),
-        //         e => e,
-        //     })?;
-
-        // self.robot_chain
-        //     .set_joint_positions(joints)
-        //     .wrap_err("Failed to set joint positions")?;
+    /// Sets the active joints, in the order of [`Robot::active_joint_names`] (see also
+    /// [`Robot::set_joints_by_name`]).
+    pub fn set_joints(&mut self, joints: &[f32]) -> Result<()> {
+        set_chain_joints(&self.robot_chain, joints)
+    }
 
+    /// Moves every link collider (and attached object) to its pose under the current joint
+    /// positions.
+    pub(crate) fn update_link_colliders(&mut self) -> Result<()> {
         self.robot_chain.update_transforms();
 
         for link_node in self.robot_chain.iter() {
@@ -312,18 +648,12 @@ impl Robot {
                 .get(joint_name)
                 .wrap_err("Failed to map joint to link_node (internal error)")?;
 
-            // link_node.link().unwrap().
-            dbg!(trans);
-            dbg!(joint_name);
-            dbg!(link_name);
-            dbg!(&self.colliders);
-
             let collider_handles = self
                 .colliders
                 .get(link_name)
                 .wrap_err_with(|| format!("Couldn't find colliders for link: {}", link_name))?;
 
-            let trans = k_isometry_to_rapier(&trans);
+            let trans = self.base_pose * k_isometry_to_rapier(&trans);
 
             for handle in collider_handles {
                 let collider = self
@@ -332,66 +662,256 @@ impl Robot {
                     .get_mut(*handle)
                     .ok_or_eyre("cannot find collider")?;
 
-                collider.set_position(trans);
+                let offset = self
+                    .collider_offsets
+                    .get(handle)
+                    .copied()
+                    .unwrap_or_else(Isometry::identity);
+                collider.set_position(trans * offset);
             }
 
-            // link.link().
-
-            // if let Some(id) = robot_state.link_names_to_entity.get(link_name) {
-            //     // query.get_mut(*id).unwrap();
-            //     query.get_mut(*id).unwrap();
-            //     if let Ok((link, mut transform)) = query.get_mut(*id) {
-            //         dbg!(&id);
-            //         //    dbg!(&query);
-            //         *transform = Transform {
-            //             translation: [
-            //                 trans.translation.vector.x,
-            //                 trans.translation.vector.y,
-            //                 trans.translation.vector.z,
-            //             ]
-            //             .into(),
-            //             rotation: Quat::from_xyzw(
-            //                 trans.rotation.i as f32,
-            //                 trans.rotation.j as f32,
-            //                 trans.rotation.k as f32,
-            //                 trans.rotation.w as f32,
-            //             ),
-            //             ..Default::default()
-            //         };
-            //         dbg!(&transform);
-
-            //         //  * transform = trans.into();
-            //     }
-            // }
+            if !self.attached_objects.is_empty() {
+                let link_name = link_name.clone();
+                self.update_attached_colliders(&link_name, &trans)?;
+            }
         }
+        Ok(())
+    }
+
+    /// Pairs of links (by index, smaller index first) that were found in contact by the
+    /// last call to [`Robot::has_collision`].
+    pub fn colliding_link_pairs(&self) -> Vec<(usize, usize)> {
+        let owner_of = |handle| {
+            self.collision_checker
+                .collider_set
+                .get(handle)
+                .map(|collider| ColliderOwner::from_user_data(collider.user_data))
+        };
+
+        let pairs: HashSet<_> = self
+            .collision_checker
+            .colliding_pairs()
+            .filter_map(|(a, b)| match (owner_of(a), owner_of(b)) {
+                (Some(ColliderOwner::Link(a)), Some(ColliderOwner::Link(b))) => {
+                    Some((a.min(b), a.max(b)))
+                }
+                _ => None,
+            })
+            .collect();
+        pairs.into_iter().collect()
+    }
+
+    pub fn has_collision(&mut self) -> Result<CollisionResult> {
+        self.update_link_colliders()?;
 
         self.collision_checker.update();
 
-        // self.collision_checker.print_collision_info();
+        // environment collisions take precedence over self-collisions
+        let mut result = CollisionResult::Free;
+        for (a, b) in self.collision_checker.colliding_pairs() {
+            if self.is_obstacle_collider(a) || self.is_obstacle_collider(b) {
+                return Ok(CollisionResult::EnvironmentCollision);
+            }
+            result = CollisionResult::SelfCollision;
+        }
+        Ok(result)
+    }
 
-        Ok(if self.collision_checker.has_collision() {
-            CollisionResult::Collision
-        } else {
-            CollisionResult::Free
-        })
+    /// Same answer as [`Robot::has_collision`], but without building the contact graph:
+    /// candidate pairs are tested directly, in a single pass that stops at the first
+    /// collision with an obstacle. Use this when only a yes/no answer is needed (e.g. sampling-based planning);
+    /// [`Robot::colliding_link_pairs`] and [`Robot::collision_report`] are not updated by it.
+    pub fn has_collision_fast(&mut self) -> Result<CollisionResult> {
+        self.update_link_colliders()?;
+
+        let checker = &mut self.collision_checker;
+        checker.update_query_pipeline();
+
+        let is_obstacle = |collider: &Collider| {
+            ColliderOwner::from_user_data(collider.user_data) == ColliderOwner::Environment
+        };
+        // environment collisions take precedence over self-collisions: after the first
+        // self-collision, only obstacle pairs are left to test
+        let found_self_collision = Cell::new(false);
+        let mut result = CollisionResult::Free;
+        checker.for_each_colliding_pair(
+            |c1, c2| !found_self_collision.get() || is_obstacle(c1) || is_obstacle(c2),
+            |c1, c2| {
+                if is_obstacle(c1) || is_obstacle(c2) {
+                    result = CollisionResult::EnvironmentCollision;
+                    ControlFlow::Break(())
+                } else {
+                    found_self_collision.set(true);
+                    result = CollisionResult::SelfCollision;
+                    ControlFlow::Continue(())
+                }
+            },
+        )?;
+        Ok(result)
     }
+}
 
-    // fn apply_joint_state(&mut self, joint_idx: usize, position: f32) {
-    //     let joint = &self.urdf_robot.joints[joint_idx];
-    //     let link = &self.urdf_robot.links[joint.child];
-    //     let colliders = self.colliders.get(&joint.child).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::{arm, at, ball, cube_stl, slider_position, SELF_COLLIDING};
+    use urdf_rs::Vec3;
+
+    /// Checks that both collision checks agree, and returns their answer.
+    fn check(robot: &mut Robot) -> CollisionResult {
+        let result = robot.has_collision().unwrap();
+        assert_eq!(robot.has_collision_fast().unwrap(), result);
+        result
+    }
 
-    //     let joint_pose = pose_to_isometry(&joint.origin);
-    //     let link_pose = pose_to_isometry(&link.visual[0].origin);
+    #[test]
+    fn fast_check_agrees_on_self_collisions() {
+        let mut robot = arm();
+        assert_eq!(check(&mut robot), CollisionResult::Free);
 
-    //     let link_transform = joint_pose * link_pose;
+        robot.set_joints(&SELF_COLLIDING).unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::SelfCollision);
 
-    //     for (collider, handle) in colliders.iter().zip(colliders) {
-    //         self.collision_checker
-    //             .collider_set
-    //             .get_mut(handle)
-    //             .unwrap()
-    //             .set_position(link_transform);
-    //     }
-    // }
+        robot.allow_collision("link1", "slider").unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::Free);
+        robot.disallow_collision("link1", "slider").unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::SelfCollision);
+    }
+
+    #[test]
+    fn fast_check_agrees_on_environment_collisions() {
+        let mut robot = arm();
+        robot
+            .add_obstacle("obstacle", &ball(0.05), at(slider_position(&[0.0; 3])))
+            .unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::EnvironmentCollision);
+        robot.remove_obstacle("obstacle").unwrap();
+
+        // on top of link1, in collision whatever the arm does
+        robot
+            .add_obstacle("obstacle", &ball(0.05), at([0.0, 0.0, 0.63]))
+            .unwrap();
+        robot.set_joints(&SELF_COLLIDING).unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::EnvironmentCollision);
+    }
+
+    #[test]
+    fn fast_check_agrees_on_padding() {
+        let mut robot = arm();
+        // 0.1 away from the slider
+        robot
+            .add_obstacle("obstacle", &ball(0.05), at([0.6, 0.0, 0.6]))
+            .unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::Free);
+
+        robot.set_link_padding("slider", Some(0.12)).unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::EnvironmentCollision);
+        robot.set_link_padding("slider", None).unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::Free);
+    }
+
+    #[test]
+    fn capsules_are_along_z() {
+        let capsule = Geometry::Capsule {
+            radius: 0.1,
+            length: 0.4,
+        };
+        let origin = Pose {
+            xyz: Vec3([1.0, 0.0, 0.0]),
+            ..Pose::default()
+        };
+        let builders = geometry_to_colliders(&None, &capsule, &origin, MeshColliderMode::default());
+        assert_eq!(builders.len(), 1);
+        assert_eq!(builders[0].position, Isometry::translation(1.0, 0.0, 0.0));
+        let shape = builders[0].shape.as_capsule().unwrap();
+        assert_eq!(shape.radius, 0.1);
+        assert_eq!(shape.segment.a, Point::new(0.0, 0.0, -0.2));
+        assert_eq!(shape.segment.b, Point::new(0.0, 0.0, 0.2));
+    }
+
+    #[test]
+    fn capsule_obstacles_collide_at_their_end_caps() {
+        let mut robot = arm();
+        // above the slider (whose top is at z = 0.65), with the lower cap at z = 0.7
+        let above = [0.4, 0.0, 1.0];
+        let capsule = Geometry::Capsule {
+            radius: 0.1,
+            length: 0.4,
+        };
+        robot.add_obstacle("capsule", &capsule, at(above)).unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::Free);
+
+        robot
+            .set_obstacle_pose("capsule", at([0.4, 0.0, 0.94]))
+            .unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::EnvironmentCollision);
+    }
+
+    #[test]
+    fn mesh_collider_modes_apply_per_link() {
+        let mesh = cube_stl("modes");
+        let link = |name: &str| {
+            format!(
+                r#"<link name="{name}"><collision><geometry><mesh filename="{mesh}" /></geometry></collision></link>"#
+            )
+        };
+        let urdf = format!(
+            r#"<robot name="cubes">{}{}{}
+  <joint name="fixed1" type="fixed"><parent link="hull" /><child link="parts" /></joint>
+  <joint name="fixed2" type="fixed"><parent link="parts" /><child link="triangles" /></joint>
+</robot>"#,
+            link("hull"),
+            link("parts"),
+            link("triangles")
+        );
+        let option = UrdfRobotOption {
+            mesh_collider_mode: MeshColliderMode::ConvexHull,
+            link_mesh_collider_modes: HashMap::from([
+                ("parts".to_owned(), "convex_decomposition".parse().unwrap()),
+                ("triangles".to_owned(), "trimesh".parse().unwrap()),
+            ]),
+            ..Default::default()
+        };
+        let robot = Robot::from_urdf_robot_with_option(
+            urdf_rs::read_from_string(&urdf).unwrap(),
+            None,
+            &option,
+        )
+        .unwrap();
+
+        let shape_of = |link: &str| {
+            let handle = robot.colliders[link][0];
+            robot.collision_checker.collider_set[handle]
+                .shared_shape()
+                .clone()
+        };
+        assert!(shape_of("hull").as_convex_polyhedron().is_some());
+        assert!(shape_of("parts").as_compound().is_some());
+        assert!(shape_of("triangles").as_trimesh().is_some());
+        assert!("convex".parse::<MeshColliderMode>().is_err());
+    }
+
+    #[test]
+    fn disabled_links_collide_with_nothing() {
+        let mut robot = arm();
+        let extended = [0.0, 0.0, 0.5];
+        robot
+            .add_obstacle("obstacle", &ball(0.05), at(slider_position(&extended)))
+            .unwrap();
+        robot.set_link_collision_enabled("slider", false).unwrap();
+        assert!(!robot.is_link_collision_enabled("slider").unwrap());
+        assert!(robot.is_link_collision_enabled("link1").unwrap());
+
+        for joints in [SELF_COLLIDING, extended] {
+            robot.set_joints(&joints).unwrap();
+            assert_eq!(check(&mut robot), CollisionResult::Free);
+        }
+
+        robot.set_link_collision_enabled("slider", true).unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::EnvironmentCollision);
+        robot.set_joints(&SELF_COLLIDING).unwrap();
+        assert_eq!(check(&mut robot), CollisionResult::SelfCollision);
+
+        assert!(robot.set_link_collision_enabled("missing", false).is_err());
+    }
 }
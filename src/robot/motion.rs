@@ -0,0 +1,235 @@
+//! Continuous collision checking along a straight line in joint space.
+//!
+//! This uses conservative advancement: at each step the current clearance is divided by
+//! an upper bound on how fast any point of the involved links can move, which gives a
+//! step that cannot skip over a collision.
+
+use std::collections::HashMap;
+
+use eyre::{ensure, ContextCompat, Result};
+use rapier3d::math::Real;
+
+use super::{k_isometry_to_rapier, Robot};
+use crate::collision_checker::ColliderOwner;
+
+impl Robot {
+    /// Clearance (between the padded links) below which a configuration is reported as
+    /// colliding by [`Robot::check_motion`]. This is the contact criterion of
+    /// [`Robot::has_collision`], and is positive so that the advancement makes progress.
+    pub fn motion_check_tolerance(&self) -> Real {
+        self.collision_checker
+            .integration_parameters
+            .prediction_distance()
+    }
+
+    /// Checks the linear joint-space interpolation from `q_from` to `q_to` for collisions.
+    ///
+    /// Returns the fraction (in `[0, 1]`) of the motion at which the robot first comes
+    /// closer than [`Robot::motion_check_tolerance`] to a collision, or `None` if the whole
+    /// motion is collision free. Collisions can only be reported early, never missed.
+    /// The joint positions of the robot are left unchanged.
+    pub fn check_motion(&mut self, q_from: &[f32], q_to: &[f32]) -> Result<Option<f32>> {
+        ensure!(
+            q_from.len() == q_to.len(),
+            "Start and goal configurations have different sizes ({} vs {})",
+            q_from.len(),
+            q_to.len()
+        );
+
+        let original_joints = self.robot_chain.joint_positions();
+        let result = self.conservative_advancement(q_from, q_to);
+        // as-is, even if the original positions are out of the joint limits
+        self.robot_chain
+            .set_joint_positions_unchecked(&original_joints);
+        result
+    }
+
+    fn conservative_advancement(&mut self, q_from: &[f32], q_to: &[f32]) -> Result<Option<f32>> {
+        let tolerance = self.motion_check_tolerance();
+        let link_rates = self.link_motion_bounds(q_from, q_to)?;
+        // obstacles are static
+        let rate_of = |owner: ColliderOwner| link_rates.get(&owner).copied().unwrap_or(0.0);
+
+        let mut q = q_from.to_vec();
+        let mut t: Real = 0.0;
+        loop {
+            if t >= 1.0 {
+                // exactly the goal, without rounding errors of the interpolation
+                q.copy_from_slice(q_to);
+            } else {
+                for ((q, from), to) in q.iter_mut().zip(q_from).zip(q_to) {
+                    *q = from + (to - from) * t;
+                }
+            }
+            self.set_joints(&q)?;
+
+            let report = self.distance_report()?;
+
+            let mut step = Real::INFINITY;
+            for pair in &report.pairs {
+                if pair.distance < tolerance {
+                    return Ok(Some(t));
+                }
+                let rate = rate_of(pair.owner1) + rate_of(pair.owner2);
+                if rate > 0.0 {
+                    step = step.min(pair.distance / rate);
+                }
+            }
+
+            if t >= 1.0 {
+                return Ok(None);
+            }
+            // always evaluate the goal configuration itself
+            t = (t + step).min(1.0);
+        }
+    }
+
+    /// For every link (and attached object), an upper bound on the distance travelled by
+    /// any point of the link over the whole motion from `q_from` to `q_to`.
+    ///
+    /// A revolute joint moves a point by at most its rotation times the point's distance
+    /// to the joint origin, which we bound by the sum of the joint offsets down to the
+    /// link, plus the largest displacement of the prismatic joints in between over the
+    /// motion, plus the link's bounding radius.
+    fn link_motion_bounds(
+        &mut self,
+        q_from: &[f32],
+        q_to: &[f32],
+    ) -> Result<HashMap<ColliderOwner, Real>> {
+        // per-joint displacement, which also accounts for mimic joints
+        let joint_positions = |robot: &mut Robot, q: &[f32]| -> Result<HashMap<String, f32>> {
+            robot.set_joints(q)?;
+            Ok(robot
+                .robot_chain
+                .iter()
+                .filter_map(|node| {
+                    let joint = node.joint();
+                    joint.joint_position().map(|pos| (joint.name.clone(), pos))
+                })
+                .collect())
+        };
+        let to_positions = joint_positions(self, q_to)?;
+        let from_positions = joint_positions(self, q_from)?;
+        let deltas: HashMap<&str, f32> = from_positions
+            .iter()
+            .map(|(name, from)| (name.as_str(), (to_positions[name] - from).abs()))
+            .collect();
+        // positions are interpolated linearly, so the largest displacement of a prismatic
+        // joint over the motion is at one of its ends
+        let extents: HashMap<&str, f32> = from_positions
+            .iter()
+            .map(|(name, from)| (name.as_str(), from.abs().max(to_positions[name].abs())))
+            .collect();
+
+        self.update_link_colliders()?;
+
+        let mut bounds = HashMap::new();
+        for link_node in self.robot_chain.iter() {
+            let link_name = self
+                .joint_link_map
+                .get(&link_node.joint().name)
+                .wrap_err("Failed to map joint to link_node (internal error)")?;
+
+            // bounding radius of the link's colliders around the link's origin
+            let link_pose = self.base_pose
+                * k_isometry_to_rapier(
+                    &link_node
+                        .world_transform()
+                        .wrap_err("Failed to get world transform")?,
+                );
+            // objects attached to the link move with it
+            let attached: Vec<_> = self
+                .attached_objects
+                .iter()
+                .filter(|(_, object)| &object.link == link_name)
+                .collect();
+            let attached_handles = attached
+                .iter()
+                .flat_map(|(_, object)| object.colliders.iter().map(|(h, _)| h));
+
+            let mut reach: Real = 0.0;
+            for handle in self
+                .colliders
+                .get(link_name)
+                .into_iter()
+                .flatten()
+                .chain(attached_handles)
+            {
+                if let Some(collider) = self.collision_checker.collider_set.get(*handle) {
+                    let sphere = collider.shape().compute_local_bounding_sphere();
+                    let center = link_pose.inverse() * (collider.position() * sphere.center());
+                    let radius = sphere.radius() + collider.contact_skin();
+                    reach = reach.max(center.coords.norm() + radius);
+                }
+            }
+
+            let mut bound: Real = 0.0;
+            for ancestor in link_node.iter_ancestors() {
+                let joint = ancestor.joint();
+                let delta = deltas.get(joint.name.as_str()).copied().unwrap_or(0.0);
+                match joint.joint_type {
+                    k::JointType::Rotational { .. } => bound += delta * reach,
+                    k::JointType::Linear { .. } => {
+                        bound += delta;
+                        // the joints above see the link this much further away
+                        reach += extents.get(joint.name.as_str()).copied().unwrap_or(0.0);
+                    }
+                    k::JointType::Fixed => (),
+                }
+                reach += joint.origin().translation.vector.norm();
+            }
+            for (_, object) in attached {
+                bounds.insert(ColliderOwner::Attached(object.id), bound);
+            }
+            bounds.insert(ColliderOwner::Link(self.link_index(link_name)?), bound);
+        }
+        Ok(bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::robot::test_util::{arm, at, ball, slider_position};
+
+    #[test]
+    fn finds_a_collision_in_the_middle_of_a_motion() {
+        let mut robot = arm();
+        // in the way of the slider at joint1 = 0, but clear of both ends of the motion
+        robot
+            .add_obstacle("ball", &ball(0.05), at(slider_position(&[0.0; 3])))
+            .unwrap();
+        let (q_from, q_to) = ([-1.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+        assert_eq!(robot.check_motion(&q_from, &q_from).unwrap(), None);
+        assert_eq!(robot.check_motion(&q_to, &q_to).unwrap(), None);
+
+        robot.set_joints(&[0.5, 0.5, 0.5]).unwrap();
+        let t = robot.check_motion(&q_from, &q_to).unwrap().unwrap();
+        // the slider comes within the tolerance of the ball at t = 0.372, and touches it
+        // at t = 0.3747
+        assert!(t > 0.35 && t < 0.3747, "{}", t);
+        assert_eq!(robot.robot_chain.joint_positions(), [0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn accounts_for_the_extension_of_prismatic_joints() {
+        let mut robot = arm();
+        // only in the way of the slider when it is extended, 1.4 away from the joint1 axis
+        robot
+            .add_obstacle("ball", &ball(0.05), at(slider_position(&[0.5, 0.0, 1.0])))
+            .unwrap();
+
+        assert_eq!(
+            robot
+                .check_motion(&[0.0, 0.0, 0.0], &[1.0, 0.0, 0.0])
+                .unwrap(),
+            None
+        );
+        let t = robot
+            .check_motion(&[0.0, 0.0, 1.0], &[1.0, 0.0, 1.0])
+            .unwrap()
+            .unwrap();
+        // the slider comes within the tolerance of the ball at t = 0.4271, and touches it
+        // at t = 0.4286
+        assert!(t > 0.4 && t < 0.4286, "{}", t);
+    }
+}
@@ -0,0 +1,204 @@
+use eyre::{ensure, OptionExt, Result};
+use rapier3d::math::{Isometry, Real};
+use rapier3d::prelude::ColliderHandle;
+use urdf_rs::{Geometry, Pose};
+
+use super::{geometry_to_colliders, MeshColliderMode, Robot, RobotError, VoxelGrid};
+use crate::collision_checker::{ColliderBuilderActivateRobotLinkCollision, ColliderOwner};
+
+/// A static obstacle in the robot's collision world.
+#[derive(Debug, Clone)]
+pub struct Obstacle {
+    pub pose: Isometry<Real>,
+    /// Colliders of this obstacle, together with their pose relative to the obstacle's pose.
+    pub colliders: Vec<(ColliderHandle, Isometry<Real>)>,
+    /// The occupancy of the obstacle, if it is a voxel grid.
+    pub voxel_grid: Option<VoxelGrid>,
+}
+
+impl Obstacle {
+    pub fn contains(&self, handle: ColliderHandle) -> bool {
+        self.colliders.iter().any(|(h, _)| *h == handle)
+    }
+}
+
+impl Robot {
+    /// Adds a static obstacle to the collision world, replacing any existing obstacle
+    /// with the same name. Obstacles collide with every link of the robot.
+    ///
+    /// Relative mesh filenames are resolved against the current working directory.
+    pub fn add_obstacle(
+        &mut self,
+        name: &str,
+        geometry: &Geometry,
+        pose: Isometry<Real>,
+    ) -> Result<()> {
+        let mut builders = geometry_to_colliders(
+            &None,
+            geometry,
+            &Pose::default(),
+            MeshColliderMode::default(),
+        );
+        // e.g. a mesh that failed to load, which would otherwise be silently ignored
+        ensure!(
+            !builders.is_empty(),
+            "Obstacle '{}' has no collision shape (failed to load its mesh?)",
+            name
+        );
+
+        if self.obstacles.contains_key(name) {
+            self.remove_obstacle(name)?;
+        }
+
+        let colliders = builders
+            .drain(..)
+            .map(|builder| {
+                let local_pose = builder.position;
+                let collider = builder
+                    .position(pose * local_pose)
+                    .activate_as_environment_obstacle()
+                    .build();
+                (
+                    self.collision_checker.collider_set.insert(collider),
+                    local_pose,
+                )
+            })
+            .collect();
+
+        self.obstacles.insert(
+            name.to_owned(),
+            Obstacle {
+                pose,
+                colliders,
+                voxel_grid: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Moves an existing obstacle to a new pose.
+    pub fn set_obstacle_pose(&mut self, name: &str, pose: Isometry<Real>) -> Result<()> {
+        let obstacle = self
+            .obstacles
+            .get_mut(name)
+            .ok_or_else(|| RobotError::UnknownObstacle(name.to_owned()))?;
+
+        obstacle.pose = pose;
+        for (handle, local_pose) in &obstacle.colliders {
+            self.collision_checker
+                .collider_set
+                .get_mut(*handle)
+                .ok_or_eyre("cannot find collider")?
+                .set_position(pose * local_pose);
+        }
+        Ok(())
+    }
+
+    /// Removes an obstacle (and all of its colliders) from the collision world.
+    pub fn remove_obstacle(&mut self, name: &str) -> Result<()> {
+        let obstacle = self
+            .obstacles
+            .remove(name)
+            .ok_or_else(|| RobotError::UnknownObstacle(name.to_owned()))?;
+
+        let checker = &mut self.collision_checker;
+        for (handle, _) in obstacle.colliders {
+            checker.collider_set.remove(
+                handle,
+                &mut checker.island_manager,
+                &mut checker.rigid_body_set,
+                false,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn is_obstacle_collider(&self, handle: ColliderHandle) -> bool {
+        self.collision_checker
+            .collider_set
+            .get(handle)
+            .is_some_and(|collider| {
+                ColliderOwner::from_user_data(collider.user_data) == ColliderOwner::Environment
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::{arm, at, ball, cuboid, slider_position};
+    use crate::robot::CollisionResult;
+
+    #[test]
+    fn obstacles_collide_where_they_are() {
+        let mut robot = arm();
+        let slider = at(slider_position(&[0.0; 3]));
+        robot.add_obstacle("obstacle", &ball(0.05), slider).unwrap();
+        assert_eq!(
+            robot.has_collision().unwrap(),
+            CollisionResult::EnvironmentCollision
+        );
+        let handle = robot.obstacles["obstacle"].colliders[0].0;
+        assert!(robot.is_obstacle_collider(handle));
+
+        robot
+            .set_obstacle_pose("obstacle", at([2.0, 0.0, 0.0]))
+            .unwrap();
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+        robot.set_obstacle_pose("obstacle", slider).unwrap();
+        assert_eq!(
+            robot.has_collision().unwrap(),
+            CollisionResult::EnvironmentCollision
+        );
+
+        // moving the arm away from the obstacle
+        robot.set_joints(&[1.0, 0.0, 0.0]).unwrap();
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+    }
+
+    #[test]
+    fn obstacles_do_not_collide_with_each_other() {
+        let mut robot = arm();
+        robot
+            .add_obstacle("a", &cuboid(0.2, 0.2, 0.2), at([2.0, 0.0, 0.0]))
+            .unwrap();
+        robot
+            .add_obstacle("b", &ball(0.2), at([2.0, 0.1, 0.0]))
+            .unwrap();
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+    }
+
+    #[test]
+    fn obstacles_are_replaced_and_removed_by_name() {
+        let mut robot = arm();
+        let num_colliders = robot.collision_checker.collider_set.len();
+
+        robot
+            .add_obstacle("obstacle", &ball(0.05), at(slider_position(&[0.0; 3])))
+            .unwrap();
+        robot
+            .add_obstacle("obstacle", &ball(0.05), at([2.0, 0.0, 0.0]))
+            .unwrap();
+        assert_eq!(robot.obstacles.len(), 1);
+        assert_eq!(
+            robot.collision_checker.collider_set.len(),
+            num_colliders + 1
+        );
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+
+        robot.remove_obstacle("obstacle").unwrap();
+        assert_eq!(robot.collision_checker.collider_set.len(), num_colliders);
+
+        for err in [
+            robot.remove_obstacle("obstacle").unwrap_err(),
+            robot
+                .set_obstacle_pose("obstacle", Isometry::identity())
+                .unwrap_err(),
+        ] {
+            assert!(matches!(
+                err.downcast_ref::<RobotError>(),
+                Some(RobotError::UnknownObstacle(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Safety margins around the links of the robot.
+//!
+//! Padding inflates a link by a given distance in every direction, for primitives and
+//! meshes alike. It is implemented with the colliders' contact skin, so it can be changed
+//! at any time without rebuilding any shape.
+
+use std::collections::HashMap;
+
+use eyre::{ensure, OptionExt, Result};
+use rapier3d::math::Real;
+
+use super::{Robot, RobotError};
+
+impl Robot {
+    /// Padding of the given link, i.e. its own padding if it has one, or the global one.
+    pub fn padding_of(&self, link_name: &str) -> Real {
+        self.link_padding
+            .get(link_name)
+            .copied()
+            .unwrap_or(self.padding)
+    }
+
+    /// The padding of links without their own padding.
+    pub fn padding(&self) -> Real {
+        self.padding
+    }
+
+    /// Sets the padding of every link that does not have its own padding. Two links are
+    /// in collision when they are closer than the sum of their paddings, and a link and an
+    /// obstacle when they are closer than the link's padding.
+    pub fn set_padding(&mut self, padding: Real) -> Result<()> {
+        ensure!(
+            padding >= 0.0,
+            "Padding cannot be negative (got {})",
+            padding
+        );
+
+        self.padding = padding;
+        let link_names: Vec<_> = self
+            .urdf_robot
+            .links
+            .iter()
+            .filter(|link| !self.link_padding.contains_key(&link.name))
+            .map(|link| link.name.clone())
+            .collect();
+        for link_name in link_names {
+            self.apply_padding(&link_name)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the padding of a single link, overriding the global padding, or reverts the
+    /// link to the global padding with `None`.
+    pub fn set_link_padding(&mut self, link_name: &str, padding: Option<Real>) -> Result<()> {
+        self.link_index(link_name)?;
+        match padding {
+            Some(padding) => {
+                ensure!(
+                    padding >= 0.0,
+                    "Padding cannot be negative (got {})",
+                    padding
+                );
+                self.link_padding.insert(link_name.to_owned(), padding);
+            }
+            None => {
+                self.link_padding.remove(link_name);
+            }
+        }
+        self.apply_padding(link_name)
+    }
+
+    /// Links with their own padding.
+    pub fn link_paddings(&self) -> &HashMap<String, Real> {
+        &self.link_padding
+    }
+
+    fn apply_padding(&mut self, link_name: &str) -> Result<()> {
+        let padding = self.padding_of(link_name);
+        let handles = self
+            .colliders
+            .get(link_name)
+            .ok_or_else(|| RobotError::UnknownLink(link_name.to_owned()))?;
+        for handle in handles {
+            self.collision_checker
+                .collider_set
+                .get_mut(*handle)
+                .ok_or_eyre("cannot find collider")?
+                .set_contact_skin(padding);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::{arm, at, ball};
+    use crate::robot::CollisionResult;
+
+    /// The arm with an obstacle 0.1 away from the slider (and 0.15 from `link2`).
+    fn near_miss() -> Robot {
+        let mut robot = arm();
+        robot
+            .add_obstacle("obstacle", &ball(0.05), at([0.6, 0.0, 0.6]))
+            .unwrap();
+        robot
+    }
+
+    #[test]
+    fn padding_turns_a_near_miss_into_a_collision() {
+        let mut robot = near_miss();
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+
+        robot.set_padding(0.12).unwrap();
+        assert_eq!(robot.padding_of("slider"), 0.12);
+        assert_eq!(
+            robot.has_collision().unwrap(),
+            CollisionResult::EnvironmentCollision
+        );
+        let closest = robot
+            .distance_report()
+            .unwrap()
+            .min_distance()
+            .unwrap()
+            .clone();
+        assert_eq!(closest.name1, "slider");
+        assert!((closest.distance + 0.02).abs() < 1e-5);
+
+        robot.set_padding(0.0).unwrap();
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+    }
+
+    #[test]
+    fn link_padding_overrides_the_global_padding() {
+        let mut robot = near_miss();
+        robot.set_link_padding("slider", Some(0.12)).unwrap();
+        assert_eq!(
+            robot.has_collision().unwrap(),
+            CollisionResult::EnvironmentCollision
+        );
+        robot.set_link_padding("slider", None).unwrap();
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+
+        robot.set_padding(0.12).unwrap();
+        robot.set_link_padding("slider", Some(0.0)).unwrap();
+        assert_eq!(robot.padding_of("slider"), 0.0);
+        assert_eq!(robot.padding_of("link2"), 0.12);
+        assert_eq!(robot.has_collision().unwrap(), CollisionResult::Free);
+        robot.set_link_padding("slider", None).unwrap();
+        assert!(robot.link_paddings().is_empty());
+        assert_eq!(
+            robot.has_collision().unwrap(),
+            CollisionResult::EnvironmentCollision
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_padding() {
+        let mut robot = arm();
+        assert!(robot.set_padding(-0.1).is_err());
+        assert!(robot.set_link_padding("slider", Some(-0.1)).is_err());
+        assert!(robot.set_link_padding("missing", Some(0.1)).is_err());
+    }
+}
@@ -1,20 +1,118 @@
-use bevy::{prelude::*, utils::HashMap};
+use std::collections::HashMap;
 
+use bevy::prelude::*;
+use urdf_rs::{Geometry, Pose};
+
+use crate::robot_vis::visuals::{pose_to_transform, primitive_bundle};
 use crate::robot_vis::{RobotLink, RobotState};
 
-use super::Robot;
+use super::{pose_to_isometry, BaseJoint, IkOption, IkTarget, Robot, RobotScene, UrdfRobotOption};
 
+/// Every robot entity (with a [`RobotState`]) within one collision scene, so that robots
+/// are also checked against each other.
 #[derive(Resource, Default)]
-struct RobotToCollisionChecker(HashMap<Entity, Robot>);
+pub(crate) struct RobotToCollisionChecker(RobotScene<Entity>);
+
+impl RobotToCollisionChecker {
+    /// For every robot, each link (in urdf order) and whether its collisions are enabled.
+    pub(crate) fn link_collisions_enabled(&self) -> HashMap<Entity, Vec<(String, bool)>> {
+        self.0
+            .robots()
+            .map(|entry| {
+                let links = entry
+                    .robot
+                    .urdf_robot
+                    .links
+                    .iter()
+                    .map(|link| {
+                        let enabled = entry
+                            .robot
+                            .is_link_collision_enabled(&link.name)
+                            .unwrap_or(true);
+                        (link.name.clone(), enabled)
+                    })
+                    .collect();
+                (entry.key, links)
+            })
+            .collect()
+    }
+}
 
 #[derive(Component, Default, Reflect)]
 struct RobotLinkIsColliding;
 
+/// Attaches an object to a link of a robot (the entity with the [`RobotState`]), both for
+/// collision checking and in the visualisation. See [`Robot::attach_object`].
+#[derive(Event, Debug, Clone)]
+pub struct AttachObject {
+    pub robot: Entity,
+    pub name: String,
+    pub link: String,
+    pub geometry: Geometry,
+    /// Pose of the object relative to the link.
+    pub pose: Pose,
+    pub touch_links: Vec<String>,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct DetachObject {
+    pub robot: Entity,
+    pub name: String,
+}
+
+/// Enables or disables every collision of a link of a robot (the entity with the
+/// [`RobotState`]). See [`Robot::set_link_collision_enabled`].
+#[derive(Event, Debug, Clone)]
+pub struct SetLinkCollisionEnabled {
+    pub robot: Entity,
+    pub link: String,
+    pub enabled: bool,
+}
+
+/// Moves a robot (the entity with the [`RobotState`]) so that a link reaches a target, with
+/// [`Robot::inverse_kinematics`] starting from the current joint positions.
+#[derive(Event, Debug, Clone)]
+pub struct SolveIk {
+    pub robot: Entity,
+    pub link: String,
+    /// Target pose of the link, in the world frame.
+    pub target: Pose,
+    /// Only reach the position of the target, with any orientation.
+    pub position_only: bool,
+    /// Only accept collision free solutions.
+    pub reject_colliding: bool,
+}
+
+/// Visual of an attached object, spawned as a child of the link entity.
+#[derive(Component, Debug)]
+pub struct AttachedObjectVisual {
+    pub robot: Entity,
+    pub name: String,
+}
+
 pub fn plugin(app: &mut App) {
     app.register_type::<RobotLinkIsColliding>()
-        .add_systems(Update, (on_new_robot_root, on_robot_change).chain())
+        .add_event::<AttachObject>()
+        .add_event::<DetachObject>()
+        .add_event::<SetLinkCollisionEnabled>()
+        .add_event::<SolveIk>()
+        .add_systems(
+            Update,
+            (
+                on_new_robot_root,
+                (
+                    on_detach_object,
+                    on_attach_object,
+                    on_set_link_collision_enabled,
+                    on_solve_ik,
+                )
+                    .chain(),
+                on_robot_change,
+            )
+                .chain(),
+        )
         .add_systems(Update, show_colliding_link_color)
-        .add_systems(Update, detect_removals)
+        .add_systems(Update, (detect_removals, on_robot_removed))
         .insert_resource(RobotToCollisionChecker::default());
 }
 
@@ -72,13 +170,35 @@ fn on_robot_change(
     robots: Query<(&RobotState, Entity), Changed<RobotState>>,
     mut robot_to_collision_checker: ResMut<RobotToCollisionChecker>,
 ) {
-    let robot_to_collision_checker = &mut robot_to_collision_checker.into_inner().0;
+    if robots.is_empty() {
+        return;
+    }
+
+    let scene = &mut robot_to_collision_checker.into_inner().0;
     for (robot_state, entity) in &robots {
-        let robot = robot_to_collision_checker.get_mut(&entity).unwrap();
+        let Ok(robot) = scene.robot_mut(&entity) else {
+            continue;
+        };
+        robot.set_base_pose(robot_state.base_pose);
+        if let Err(e) = robot.set_joints(robot_state.robot_chain.joint_positions().as_slice()) {
+            warn!("Failed to set joints of {:?}: {}", entity, e);
+        }
+    }
 
-        robot.set_joints(robot_state.robot_chain.joint_positions().as_slice());
+    match scene.has_collision() {
+        Ok(result) => debug!("{:?}", result),
+        Err(e) => error!("Failed to check collisions: {}", e),
+    }
+}
 
-        dbg!(robot.has_collision().unwrap());
+fn on_robot_removed(
+    mut removals: RemovedComponents<RobotState>,
+    mut robot_to_collision_checker: ResMut<RobotToCollisionChecker>,
+) {
+    for entity in removals.read() {
+        if robot_to_collision_checker.0.contains(&entity) {
+            let _ = robot_to_collision_checker.0.remove_robot(&entity);
+        }
     }
 }
 
@@ -87,16 +207,191 @@ fn on_new_robot_root(
     mut robot_to_collision_checker: ResMut<RobotToCollisionChecker>,
 ) {
     for (robot_state, entity) in &robots {
-        if !robot_to_collision_checker.0.contains_key(&entity) {
-            robot_to_collision_checker.0.insert(
-                entity,
-                Robot::from_urdf_robot(robot_state.urdf_robot.clone(), None).unwrap(), // TODO make urd_robot contains the base_dir
+        if robot_to_collision_checker.0.contains(&entity) {
+            continue;
+        }
+
+        let option = UrdfRobotOption {
+            // the urdf of the state already has its virtual base joints
+            base_joint: BaseJoint::Fixed,
+            ..robot_state.robot_option.clone()
+        };
+        let robot = match Robot::from_urdf_robot_with_option(
+            robot_state.urdf_robot.clone(),
+            robot_state.base_dir.as_deref(),
+            &option,
+        ) {
+            Ok(robot) => robot,
+            Err(e) => {
+                error!("Failed to build the collision model of {:?}: {}", entity, e);
+                continue;
+            }
+        };
+        if let Err(e) = robot_to_collision_checker
+            .0
+            .add_robot(entity, robot, robot_state.base_pose)
+        {
+            error!("Failed to add {:?} to the collision scene: {}", entity, e);
+        }
+    }
+}
+
+fn despawn_attached_visual(
+    commands: &mut Commands,
+    visuals: &Query<(Entity, &AttachedObjectVisual)>,
+    robot: Entity,
+    name: &str,
+) {
+    for (entity, visual) in visuals {
+        if visual.robot == robot && visual.name == name {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn on_attach_object(
+    mut commands: Commands,
+    mut reader: EventReader<AttachObject>,
+    robots: Query<&RobotState>,
+    visuals: Query<(Entity, &AttachedObjectVisual)>,
+    mut robot_to_collision_checker: ResMut<RobotToCollisionChecker>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for event in reader.read() {
+        let (Some(robot), Ok(robot_state)) = (
+            robot_to_collision_checker.0.robot_mut(&event.robot).ok(),
+            robots.get(event.robot),
+        ) else {
+            error!(
+                "Cannot attach '{}' to unknown robot {:?}",
+                event.name, event.robot
             );
+            continue;
+        };
+
+        let touch_links: Vec<&str> = event.touch_links.iter().map(String::as_str).collect();
+        if let Err(e) = robot.attach_object(
+            &event.name,
+            &event.link,
+            &event.geometry,
+            pose_to_isometry(&event.pose),
+            &touch_links,
+        ) {
+            error!("Failed to attach '{}': {}", event.name, e);
+            continue;
         }
 
-        // let kinematic: &k::Chain<f32> = &robot_state.robot_chain;
+        // replaces any previous visual of an object with the same name
+        despawn_attached_visual(&mut commands, &visuals, event.robot, &event.name);
 
-        // kinematic.update_transforms();
-        // for link in kinematic.iter() {}
+        let Some(&link_entity) = robot_state.link_names_to_entity.get(&event.link) else {
+            continue;
+        };
+        let Some(bundle) = primitive_bundle(&event.geometry, &mut meshes) else {
+            warn!(
+                "Attached object '{}' is a mesh, which is not visualised",
+                event.name
+            );
+            continue;
+        };
+        let material = materials.add(StandardMaterial {
+            base_color: Color::srgb(0.2, 0.6, 0.9),
+            ..default()
+        });
+
+        commands.entity(link_entity).with_children(|builder| {
+            builder
+                .spawn(AttachedObjectVisual {
+                    robot: event.robot,
+                    name: event.name.clone(),
+                })
+                .insert(Name::new(format!("{}_attached", event.name)))
+                .insert(SpatialBundle::from_transform(pose_to_transform(
+                    &event.pose,
+                )))
+                .with_children(|builder| {
+                    builder.spawn(PbrBundle { material, ..bundle });
+                });
+        });
+    }
+}
+
+fn on_detach_object(
+    mut commands: Commands,
+    mut reader: EventReader<DetachObject>,
+    visuals: Query<(Entity, &AttachedObjectVisual)>,
+    mut robot_to_collision_checker: ResMut<RobotToCollisionChecker>,
+) {
+    for event in reader.read() {
+        if let Ok(robot) = robot_to_collision_checker.0.robot_mut(&event.robot) {
+            if let Err(e) = robot.detach_object(&event.name) {
+                error!("Failed to detach '{}': {}", event.name, e);
+            }
+        }
+        despawn_attached_visual(&mut commands, &visuals, event.robot, &event.name);
+    }
+}
+
+fn on_set_link_collision_enabled(
+    mut reader: EventReader<SetLinkCollisionEnabled>,
+    mut robots: Query<&mut RobotState>,
+    mut robot_to_collision_checker: ResMut<RobotToCollisionChecker>,
+) {
+    for event in reader.read() {
+        let Ok(robot) = robot_to_collision_checker.0.robot_mut(&event.robot) else {
+            error!(
+                "Cannot change the collisions of '{}' of unknown robot {:?}",
+                event.link, event.robot
+            );
+            continue;
+        };
+        if let Err(e) = robot.set_link_collision_enabled(&event.link, event.enabled) {
+            error!("Failed to change the collisions of '{}': {}", event.link, e);
+            continue;
+        }
+        // check the robot again with the new filtering
+        if let Ok(mut robot_state) = robots.get_mut(event.robot) {
+            robot_state.set_changed();
+        }
+    }
+}
+
+fn on_solve_ik(
+    mut reader: EventReader<SolveIk>,
+    mut robots: Query<&mut RobotState>,
+    mut robot_to_collision_checker: ResMut<RobotToCollisionChecker>,
+) {
+    for event in reader.read() {
+        let (Ok(robot), Ok(mut robot_state)) = (
+            robot_to_collision_checker.0.robot_mut(&event.robot),
+            robots.get_mut(event.robot),
+        ) else {
+            error!("Cannot solve IK of unknown robot {:?}", event.robot);
+            continue;
+        };
+
+        let pose = pose_to_isometry(&event.target);
+        let target = if event.position_only {
+            IkTarget::Position(pose.translation.vector.into())
+        } else {
+            IkTarget::pose(pose)
+        };
+        let option = IkOption {
+            seed: Some(robot_state.robot_chain.joint_positions()),
+            reject_colliding: event.reject_colliding,
+            ..default()
+        };
+        match robot.inverse_kinematics(&event.link, &target, &option) {
+            Ok(Some(joints)) => {
+                match robot_state.robot_chain.set_joint_positions(&joints) {
+                    // the chain is shared behind a lock, so mark the change explicitly
+                    Ok(()) => robot_state.set_changed(),
+                    Err(e) => error!("Failed to apply the IK solution: {}", e),
+                }
+            }
+            Ok(None) => warn!("No IK solution found for '{}'", event.link),
+            Err(e) => error!("Failed to solve IK for '{}': {}", event.link, e),
+        }
     }
 }
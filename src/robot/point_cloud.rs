@@ -0,0 +1,705 @@
+//! Point clouds (e.g. from a depth camera) as obstacles.
+//!
+//! A point cloud is stored as a single obstacle: a compound of small balls, one per
+//! (filtered) point. Clouds can be read from PLY and PCD files.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use eyre::{bail, ensure, Context, ContextCompat, OptionExt, Result};
+use rapier3d::math::{Isometry, Point, Real};
+use rapier3d::parry::bounding_volume::Aabb;
+use rapier3d::prelude::{ColliderBuilder, SharedShape};
+
+use super::{Obstacle, Robot};
+use crate::collision_checker::{ColliderBuilderActivateRobotLinkCollision, ColliderOwner};
+
+/// How a point cloud is turned into an obstacle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointCloudOption {
+    /// Radius of the ball placed at every point.
+    pub radius: Real,
+    /// If set, the points are downsampled to (at most) one per voxel of this size, at the
+    /// centroid of the points in the voxel.
+    pub voxel_size: Option<Real>,
+    /// Removes the points that lie on the robot itself (its links and attached objects,
+    /// at the current joint positions), e.g. when the camera sees the arm.
+    pub remove_robot_points: bool,
+    /// Points closer than this to the robot are considered to be on the robot.
+    pub robot_margin: Real,
+}
+
+impl Default for PointCloudOption {
+    fn default() -> Self {
+        Self {
+            radius: 0.01,
+            voxel_size: None,
+            remove_robot_points: false,
+            robot_margin: 0.02,
+        }
+    }
+}
+
+impl Robot {
+    /// Adds a point cloud as an obstacle, replacing any existing obstacle with the same
+    /// name. `points` are given in the frame of the cloud, which is placed at `pose`.
+    /// Returns the number of points kept after filtering.
+    pub fn add_point_cloud_obstacle(
+        &mut self,
+        name: &str,
+        points: &[Point<Real>],
+        pose: Isometry<Real>,
+        option: &PointCloudOption,
+    ) -> Result<usize> {
+        ensure!(
+            option.radius > 0.0,
+            "Point radius must be positive (got {})",
+            option.radius
+        );
+
+        let mut points: Vec<_> = points
+            .iter()
+            .filter(|p| p.iter().all(|v| v.is_finite()))
+            .copied()
+            .collect();
+        if let Some(voxel_size) = option.voxel_size {
+            points = voxel_downsample(&points, voxel_size)?;
+        }
+        if option.remove_robot_points {
+            points = self.remove_robot_points(points, &pose, option.robot_margin)?;
+        }
+
+        if self.obstacles.contains_key(name) {
+            self.remove_obstacle(name)?;
+        }
+
+        let mut colliders = Vec::new();
+        if !points.is_empty() {
+            let ball = SharedShape::ball(option.radius);
+            let shape = SharedShape::compound(
+                points
+                    .iter()
+                    .map(|p| (Isometry::translation(p.x, p.y, p.z), ball.clone()))
+                    .collect(),
+            );
+            let collider = ColliderBuilder::new(shape)
+                .position(pose)
+                .activate_as_environment_obstacle()
+                .build();
+            colliders.push((
+                self.collision_checker.collider_set.insert(collider),
+                Isometry::identity(),
+            ));
+        }
+
+        self.obstacles.insert(
+            name.to_owned(),
+            Obstacle {
+                pose,
+                colliders,
+                voxel_grid: None,
+            },
+        );
+        Ok(points.len())
+    }
+
+    /// Reads a PLY or PCD file (by its extension) and adds it with
+    /// [`Robot::add_point_cloud_obstacle`].
+    pub fn add_point_cloud_obstacle_from_file(
+        &mut self,
+        name: &str,
+        path: &str,
+        pose: Isometry<Real>,
+        option: &PointCloudOption,
+    ) -> Result<usize> {
+        let points = read_point_cloud(path)?;
+        self.add_point_cloud_obstacle(name, &points, pose, option)
+    }
+
+    /// Keeps the points (in the frame given by `pose`) that are further than `margin` from
+    /// every link and attached object.
+    fn remove_robot_points(
+        &mut self,
+        points: Vec<Point<Real>>,
+        pose: &Isometry<Real>,
+        margin: Real,
+    ) -> Result<Vec<Point<Real>>> {
+        self.update_link_colliders()?;
+        self.collision_checker.update_exact_query_pipeline();
+
+        let checker = &self.collision_checker;
+        Ok(points
+            .into_iter()
+            .filter(|local_point| {
+                let point = pose * local_point;
+                let mut on_robot = false;
+                checker
+                    .query_pipeline
+                    .colliders_with_aabb_intersecting_aabb(
+                        &Aabb::new(point, point).loosened(margin),
+                        |handle| {
+                            let Some(collider) = checker.collider_set.get(*handle) else {
+                                return true;
+                            };
+                            if ColliderOwner::from_user_data(collider.user_data)
+                                != ColliderOwner::Environment
+                            {
+                                on_robot = collider.shape().distance_to_point(
+                                    collider.position(),
+                                    &point,
+                                    true,
+                                ) <= margin;
+                            }
+                            !on_robot
+                        },
+                    );
+                !on_robot
+            })
+            .collect())
+    }
+}
+
+/// Replaces the points in every voxel of the given size by their centroid.
+pub fn voxel_downsample(points: &[Point<Real>], voxel_size: Real) -> Result<Vec<Point<Real>>> {
+    ensure!(
+        voxel_size > 0.0,
+        "Voxel size must be positive (got {})",
+        voxel_size
+    );
+
+    // keep the order of first occurrence, so that the result is deterministic
+    let mut voxels: HashMap<[i64; 3], usize> = HashMap::new();
+    let mut centroids: Vec<(Point<Real>, usize)> = Vec::new();
+    for p in points {
+        let key = [0, 1, 2].map(|i| (p[i] / voxel_size).floor() as i64);
+        let idx = *voxels.entry(key).or_insert_with(|| {
+            centroids.push((Point::origin(), 0));
+            centroids.len() - 1
+        });
+        let (sum, count) = &mut centroids[idx];
+        sum.coords += p.coords;
+        *count += 1;
+    }
+    Ok(centroids
+        .into_iter()
+        .map(|(sum, count)| sum / count as Real)
+        .collect())
+}
+
+/// Reads the points of a PLY or PCD file, depending on its extension. Points with
+/// non-finite coordinates (e.g. invalid depth readings) are skipped.
+pub fn read_point_cloud(path: &str) -> Result<Vec<Point<Real>>> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    let file = File::open(path).wrap_err_with(|| format!("Failed to open {}", path))?;
+    let mut reader = BufReader::new(file);
+    let points = match extension.as_deref() {
+        Some("ply") => read_ply(&mut reader),
+        Some("pcd") => read_pcd(&mut reader),
+        _ => bail!(
+            "Unsupported point cloud format: {} (expected .ply or .pcd)",
+            path
+        ),
+    }
+    .wrap_err_with(|| format!("Failed to read point cloud {}", path))?;
+
+    Ok(points
+        .into_iter()
+        .filter(|p| p.iter().all(|v| v.is_finite()))
+        .collect())
+}
+
+/// A scalar type of a binary point cloud file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn from_ply(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => bail!("Unknown PLY property type '{}'", name),
+        })
+    }
+
+    fn from_pcd(kind: &str, size: usize) -> Result<Self> {
+        Ok(match (kind, size) {
+            ("I", 1) => Scalar::I8,
+            ("U", 1) => Scalar::U8,
+            ("I", 2) => Scalar::I16,
+            ("U", 2) => Scalar::U16,
+            ("I", 4) => Scalar::I32,
+            ("U", 4) => Scalar::U32,
+            ("F", 4) => Scalar::F32,
+            ("F", 8) => Scalar::F64,
+            _ => bail!("Unsupported PCD field type {} of size {}", kind, size),
+        })
+    }
+
+    fn read(self, bytes: &[u8], little_endian: bool) -> Real {
+        macro_rules! decode {
+            ($ty:ty) => {{
+                let bytes = bytes.try_into().unwrap();
+                if little_endian {
+                    <$ty>::from_le_bytes(bytes) as Real
+                } else {
+                    <$ty>::from_be_bytes(bytes) as Real
+                }
+            }};
+        }
+        match self {
+            Scalar::I8 => decode!(i8),
+            Scalar::U8 => decode!(u8),
+            Scalar::I16 => decode!(i16),
+            Scalar::U16 => decode!(u16),
+            Scalar::I32 => decode!(i32),
+            Scalar::U32 => decode!(u32),
+            Scalar::F32 => decode!(f32),
+            Scalar::F64 => decode!(f64),
+        }
+    }
+}
+
+/// Where x, y and z are within a point of a file.
+struct PointLayout {
+    /// byte offsets of x, y, z, for binary data
+    offsets: [usize; 3],
+    /// columns of x, y, z, for ascii data
+    columns: [usize; 3],
+    scalars: [Scalar; 3],
+    /// size of a point in bytes, for binary data
+    size: usize,
+}
+
+impl PointLayout {
+    /// Layout of points made of the given fields, each with its type and count.
+    fn new<'a>(fields: impl IntoIterator<Item = (&'a str, Scalar, usize)>) -> Result<Self> {
+        let mut xyz = [None; 3];
+        let (mut offset, mut column) = (0, 0);
+        for (name, scalar, count) in fields {
+            if let Some(axis) = ["x", "y", "z"].iter().position(|axis| *axis == name) {
+                xyz[axis] = Some((offset, column, scalar));
+            }
+            offset = scalar
+                .size()
+                .checked_mul(count)
+                .and_then(|size| offset.checked_add(size))
+                .ok_or_eyre("Point size overflows")?;
+            column += count;
+        }
+        let mut layout = Self {
+            offsets: [0; 3],
+            columns: [0; 3],
+            scalars: [Scalar::F32; 3],
+            size: offset,
+        };
+        for (axis, field) in xyz.into_iter().enumerate() {
+            let (offset, column, scalar) =
+                field.wrap_err_with(|| format!("Missing {} field", ["x", "y", "z"][axis]))?;
+            layout.offsets[axis] = offset;
+            layout.columns[axis] = column;
+            layout.scalars[axis] = scalar;
+        }
+        Ok(layout)
+    }
+
+    fn read_binary(&self, bytes: &[u8], little_endian: bool) -> Point<Real> {
+        Point::from([0, 1, 2].map(|axis| {
+            let (offset, scalar) = (self.offsets[axis], self.scalars[axis]);
+            scalar.read(&bytes[offset..offset + scalar.size()], little_endian)
+        }))
+    }
+
+    fn read_ascii(&self, line: &str) -> Result<Point<Real>> {
+        let values: Vec<&str> = line.split_whitespace().collect();
+        let mut point = Point::origin();
+        for axis in 0..3 {
+            let value = values
+                .get(self.columns[axis])
+                .wrap_err_with(|| format!("Too few values in line '{}'", line))?;
+            // "nan" is valid in PCD files
+            point[axis] = value
+                .parse::<Real>()
+                .wrap_err_with(|| format!("Invalid value '{}'", value))?;
+        }
+        Ok(point)
+    }
+
+    fn read_points(
+        &self,
+        reader: &mut impl BufRead,
+        count: usize,
+        encoding: Encoding,
+    ) -> Result<Vec<Point<Real>>> {
+        match encoding {
+            Encoding::Ascii => (0..count)
+                .map(|_| self.read_ascii(&read_line(reader)?))
+                .collect(),
+            Encoding::Binary { little_endian } => {
+                let size = count.checked_mul(self.size).ok_or_eyre("Too many points")?;
+                // the header cannot be trusted with the size of an allocation, so read what
+                // the file holds and check that against it
+                let mut data = Vec::new();
+                reader
+                    .read_to_end(&mut data)
+                    .wrap_err("Failed to read point data")?;
+                ensure!(
+                    data.len() >= size,
+                    "Unexpected end of point data ({} points of {} bytes need {} bytes, got {})",
+                    count,
+                    self.size,
+                    size,
+                    data.len()
+                );
+                Ok(data[..size]
+                    .chunks_exact(self.size)
+                    .map(|bytes| self.read_binary(bytes, little_endian))
+                    .collect())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Ascii,
+    Binary { little_endian: bool },
+}
+
+/// Reads a line, without its line ending.
+fn read_line(reader: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    ensure!(reader.read_line(&mut line)? > 0, "Unexpected end of file");
+    Ok(line.trim_end().to_owned())
+}
+
+/// Reads the vertices of a PLY file, in any of the ascii and binary formats.
+fn read_ply(reader: &mut impl BufRead) -> Result<Vec<Point<Real>>> {
+    ensure!(read_line(reader)? == "ply", "Not a PLY file");
+
+    let mut encoding = None;
+    // every element with its count and its properties (name, type, whether it is a list)
+    let mut elements: Vec<(String, usize, Vec<(String, Scalar, bool)>)> = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", format, _] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::Binary {
+                        little_endian: true,
+                    },
+                    "binary_big_endian" => Encoding::Binary {
+                        little_endian: false,
+                    },
+                    _ => bail!("Unknown PLY format '{}'", format),
+                })
+            }
+            ["element", name, count] => {
+                elements.push((name.to_string(), count.parse()?, Vec::new()));
+            }
+            ["property", "list", _, item, name] => elements
+                .last_mut()
+                .ok_or_eyre("Property before any element")?
+                .2
+                .push((name.to_string(), Scalar::from_ply(item)?, true)),
+            ["property", scalar, name] => elements
+                .last_mut()
+                .ok_or_eyre("Property before any element")?
+                .2
+                .push((name.to_string(), Scalar::from_ply(scalar)?, false)),
+            _ => (), // comments, obj_info
+        }
+    }
+    let encoding = encoding.ok_or_eyre("Missing PLY format")?;
+
+    let vertex_idx = elements
+        .iter()
+        .position(|(name, _, _)| name == "vertex")
+        .ok_or_eyre("No vertex element")?;
+    // elements are stored one after the other, so skip the ones before the vertices
+    for (name, count, _) in &elements[..vertex_idx] {
+        ensure!(
+            encoding == Encoding::Ascii,
+            "Binary PLY files with element '{}' before the vertices are not supported",
+            name
+        );
+        for _ in 0..*count {
+            read_line(reader)?;
+        }
+    }
+
+    let (_, count, properties) = &elements[vertex_idx];
+    ensure!(
+        properties.iter().all(|(_, _, is_list)| !is_list),
+        "List properties of vertices are not supported"
+    );
+    PointLayout::new(
+        properties
+            .iter()
+            .map(|(name, scalar, _)| (name.as_str(), *scalar, 1)),
+    )?
+    .read_points(reader, *count, encoding)
+}
+
+/// Reads the points of a PCD file, with ascii or (uncompressed) binary data.
+fn read_pcd(reader: &mut impl BufRead) -> Result<Vec<Point<Real>>> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut sizes: Vec<usize> = Vec::new();
+    let mut types: Vec<String> = Vec::new();
+    let mut counts: Vec<usize> = Vec::new();
+    let (mut width, mut height, mut points) = (None, None, None);
+
+    let encoding = loop {
+        let line = read_line(reader)?;
+        let mut words = line.split_whitespace();
+        let Some(key) = words.next() else {
+            continue;
+        };
+        let values: Vec<&str> = words.collect();
+        match key {
+            _ if key.starts_with('#') => (),
+            "FIELDS" => fields = values.iter().map(|v| v.to_string()).collect(),
+            "SIZE" => sizes = values.iter().map(|v| v.parse()).collect::<Result<_, _>>()?,
+            "TYPE" => types = values.iter().map(|v| v.to_string()).collect(),
+            "COUNT" => counts = values.iter().map(|v| v.parse()).collect::<Result<_, _>>()?,
+            "WIDTH" => width = Some(values.first().ok_or_eyre("Missing WIDTH")?.parse()?),
+            "HEIGHT" => height = Some(values.first().ok_or_eyre("Missing HEIGHT")?.parse()?),
+            "POINTS" => points = Some(values.first().ok_or_eyre("Missing POINTS")?.parse()?),
+            "DATA" => {
+                break match values.first().copied() {
+                    Some("ascii") => Encoding::Ascii,
+                    Some("binary") => Encoding::Binary {
+                        little_endian: true,
+                    },
+                    Some(data) => bail!("Unsupported PCD data '{}'", data),
+                    None => bail!("Missing DATA"),
+                }
+            }
+            _ => (), // VERSION, VIEWPOINT
+        }
+    };
+
+    if counts.is_empty() {
+        counts = vec![1; fields.len()];
+    }
+    ensure!(
+        sizes.len() == fields.len() && types.len() == fields.len() && counts.len() == fields.len(),
+        "FIELDS, SIZE, TYPE and COUNT have different lengths"
+    );
+    let num_points: usize = match (points, width, height) {
+        (Some(points), _, _) => points,
+        (None, Some(width), Some(height)) => usize::checked_mul(width, height)
+            .ok_or_eyre("Too many points (WIDTH * HEIGHT overflows)")?,
+        _ => bail!("Missing POINTS"),
+    };
+
+    let scalars = types
+        .iter()
+        .zip(&sizes)
+        .map(|(kind, size)| Scalar::from_pcd(kind, *size))
+        .collect::<Result<Vec<_>>>()?;
+    PointLayout::new(
+        fields
+            .iter()
+            .zip(scalars)
+            .zip(&counts)
+            .map(|((name, scalar), count)| (name.as_str(), scalar, *count)),
+    )?
+    .read_points(reader, num_points, encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: Real, y: Real, z: Real) -> Point<Real> {
+        Point::new(x, y, z)
+    }
+
+    /// Header followed by binary data.
+    fn file(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn reads_ascii_ply_after_other_elements() {
+        let ply = "ply
+format ascii 1.0
+comment made by hand
+element camera 1
+property float focal
+element vertex 2
+property float x
+property float y
+property float z
+property uchar red
+element face 1
+property list uchar int vertex_indices
+end_header
+500.0
+1 2 3 255
+-1.5 0 nan 0
+3 0 1 1
+";
+        let points = read_ply(&mut ply.as_bytes()).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0], point(1.0, 2.0, 3.0));
+        assert!(points[1].z.is_nan());
+    }
+
+    #[test]
+    fn reads_binary_ply() {
+        for (format, little_endian) in
+            [("binary_little_endian", true), ("binary_big_endian", false)]
+        {
+            let header = format!(
+                "ply\nformat {} 1.0\nelement vertex 2\nproperty uchar red\n\
+                 property double x\nproperty float y\nproperty short z\nend_header\n",
+                format
+            );
+            let mut data = Vec::new();
+            for (red, x, y, z) in [(7u8, 1.5f64, -2.0f32, 3i16), (8, 0.25, 4.0, -5)] {
+                data.push(red);
+                if little_endian {
+                    data.extend(x.to_le_bytes());
+                    data.extend(y.to_le_bytes());
+                    data.extend(z.to_le_bytes());
+                } else {
+                    data.extend(x.to_be_bytes());
+                    data.extend(y.to_be_bytes());
+                    data.extend(z.to_be_bytes());
+                }
+            }
+            // faces after the vertices are not read
+            data.extend([1, 2, 3]);
+
+            let points = read_ply(&mut file(&header, &data).as_slice()).unwrap();
+            assert_eq!(
+                points,
+                vec![point(1.5, -2.0, 3.0), point(0.25, 4.0, -5.0)],
+                "{}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_binary_ply() {
+        // far more points than the file holds, which must not be allocated up front
+        let header = format!(
+            "ply\nformat binary_little_endian 1.0\nelement vertex {}\nproperty float x\n\
+             property float y\nproperty float z\nend_header\n",
+            usize::MAX / 4
+        );
+        assert!(read_ply(&mut file(&header, &[0; 12]).as_slice()).is_err());
+
+        let header = "ply\nformat binary_little_endian 1.0\nelement vertex 2\nproperty float x\n\
+                      property float y\nproperty float z\nend_header\n";
+        assert!(read_ply(&mut file(header, &[0; 12]).as_slice()).is_err());
+    }
+
+    #[test]
+    fn reads_ascii_pcd() {
+        let pcd = "# .PCD v0.7 - Point Cloud Data file format
+VERSION 0.7
+FIELDS x y z rgb
+SIZE 4 4 4 4
+TYPE F F F U
+COUNT 1 1 1 1
+WIDTH 2
+HEIGHT 1
+VIEWPOINT 0 0 0 1 0 0 0
+POINTS 2
+DATA ascii
+0.5 1 -2 4808
+nan nan nan 0
+";
+        let points = read_pcd(&mut pcd.as_bytes()).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0], point(0.5, 1.0, -2.0));
+        assert!(points[1].x.is_nan());
+    }
+
+    #[test]
+    fn reads_binary_pcd_with_multi_value_fields() {
+        // 3 normal values come before x, y and z, and are skipped as a whole
+        let header = "VERSION 0.7
+FIELDS normal x y z intensity
+SIZE 4 4 4 8 1
+TYPE F F F F U
+COUNT 3 1 1 1 1
+WIDTH 2
+HEIGHT 1
+DATA binary
+";
+        let mut data = Vec::new();
+        for (x, y, z) in [(1.0f32, 2.0f32, 3.0f64), (-4.0, 5.5, -6.0)] {
+            for normal in [0.0f32, 0.0, 1.0] {
+                data.extend(normal.to_le_bytes());
+            }
+            data.extend(x.to_le_bytes());
+            data.extend(y.to_le_bytes());
+            data.extend(z.to_le_bytes());
+            data.push(9);
+        }
+
+        let points = read_pcd(&mut file(header, &data).as_slice()).unwrap();
+        assert_eq!(points, vec![point(1.0, 2.0, 3.0), point(-4.0, 5.5, -6.0)]);
+
+        // WIDTH * HEIGHT overflowing is an error rather than a panic
+        let header = header.replace("WIDTH 2", &format!("WIDTH {}", usize::MAX));
+        let header = header.replace("HEIGHT 1", "HEIGHT 2");
+        assert!(read_pcd(&mut file(&header, &data).as_slice()).is_err());
+    }
+
+    #[test]
+    fn downsamples_to_voxel_centroids() {
+        let points = [
+            point(0.1, 0.1, 0.1),
+            point(1.5, 0.5, 0.5),
+            point(0.3, 0.5, 0.7),
+            // negative coordinates belong to the voxel below zero
+            point(-0.1, 0.5, 0.5),
+        ];
+        let downsampled = voxel_downsample(&points, 1.0).unwrap();
+        assert_eq!(downsampled.len(), 3);
+        assert!((downsampled[0] - point(0.2, 0.3, 0.4)).norm() < 1e-6);
+        assert_eq!(downsampled[1], points[1]);
+        assert_eq!(downsampled[2], points[3]);
+
+        assert!(voxel_downsample(&points, 0.0).is_err());
+    }
+}
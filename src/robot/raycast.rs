@@ -0,0 +1,176 @@
+//! Ray casting against the links, attached objects and obstacles of a robot, e.g. for
+//! simulated range sensors, visibility checks or picking.
+//!
+//! Rays hit the actual geometry: paddings are ignored.
+
+use eyre::{ensure, OptionExt, Result};
+use rapier3d::math::{Point, Real, Vector};
+use rapier3d::prelude::{QueryFilter, Ray};
+use rayon::prelude::*;
+
+use super::Robot;
+use crate::collision_checker::ColliderOwner;
+
+/// Closest hit of a ray, in the world frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RayHit {
+    /// Distance from the ray origin to the hit point.
+    pub distance: Real,
+    pub point: Point<Real>,
+    /// Outward normal of the surface at the hit point.
+    pub normal: Vector<Real>,
+    /// Name of the link, attached object or obstacle that was hit.
+    pub name: String,
+    pub owner: ColliderOwner,
+}
+
+impl Robot {
+    /// Casts a ray from `origin` along `dir`, up to `max_distance`, and returns the closest
+    /// hit. Rays starting inside a shape hit it at distance zero.
+    pub fn cast_ray(
+        &mut self,
+        origin: Point<Real>,
+        dir: Vector<Real>,
+        max_distance: Real,
+    ) -> Result<Option<RayHit>> {
+        self.update_ray_query()?;
+        self.ray_hit(origin, dir, max_distance)
+    }
+
+    /// Casts many rays (each an origin and a direction) at once, in parallel. The results
+    /// are in the same order as the rays.
+    pub fn cast_rays(
+        &mut self,
+        rays: &[(Point<Real>, Vector<Real>)],
+        max_distance: Real,
+    ) -> Result<Vec<Option<RayHit>>> {
+        self.update_ray_query()?;
+        let robot = &*self;
+        rays.par_iter()
+            .map(|(origin, dir)| robot.ray_hit(*origin, *dir, max_distance))
+            .collect()
+    }
+
+    /// Brings the colliders and the query pipeline up to date with the joints. The query
+    /// pipeline is only rebuilt if a collider has changed since the last ray cast.
+    fn update_ray_query(&mut self) -> Result<()> {
+        self.update_link_colliders()?;
+        self.collision_checker.update_exact_query_pipeline();
+        Ok(())
+    }
+
+    fn ray_hit(
+        &self,
+        origin: Point<Real>,
+        dir: Vector<Real>,
+        max_distance: Real,
+    ) -> Result<Option<RayHit>> {
+        let dir = dir
+            .try_normalize(Real::EPSILON)
+            .ok_or_eyre("Ray direction cannot be zero")?;
+        ensure!(
+            max_distance >= 0.0,
+            "Maximum ray distance cannot be negative (got {})",
+            max_distance
+        );
+
+        let checker = &self.collision_checker;
+        let ray = Ray::new(origin, dir);
+        let Some((handle, intersection)) = checker.query_pipeline.cast_ray_and_get_normal(
+            &checker.rigid_body_set,
+            &checker.collider_set,
+            &ray,
+            max_distance,
+            true,
+            QueryFilter::default(),
+        ) else {
+            return Ok(None);
+        };
+
+        let (owner, name) = self
+            .collider_owner_name(handle)
+            .ok_or_eyre("cannot find the link, attached object or obstacle of a collider")?;
+        Ok(Some(RayHit {
+            distance: intersection.time_of_impact,
+            point: ray.point_at(intersection.time_of_impact),
+            normal: intersection.normal,
+            name: name.to_owned(),
+            owner,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::test_util::{arm, at, cuboid};
+
+    fn robot_with_box() -> Robot {
+        let mut robot = arm();
+        robot
+            .add_obstacle("box", &cuboid(0.2, 0.2, 0.2), at([2.0, 0.0, 0.0]))
+            .unwrap();
+        robot
+    }
+
+    #[test]
+    fn ray_hits_the_closest_surface() {
+        let mut robot = robot_with_box();
+        let hit = robot
+            .cast_ray(Point::new(1.0, 0.0, 0.0), Vector::new(2.0, 0.0, 0.0), 10.0)
+            .unwrap()
+            .unwrap();
+        assert!((hit.distance - 0.9).abs() < 1e-5);
+        assert!((hit.point - Point::new(1.9, 0.0, 0.0)).norm() < 1e-5);
+        assert!((hit.normal - Vector::new(-1.0, 0.0, 0.0)).norm() < 1e-5);
+        assert_eq!(hit.name, "box");
+        assert_eq!(hit.owner, ColliderOwner::Environment);
+
+        let hit = robot
+            .cast_ray(Point::new(0.4, 0.0, 2.0), Vector::new(0.0, 0.0, -1.0), 10.0)
+            .unwrap()
+            .unwrap();
+        assert!((hit.distance - 1.35).abs() < 1e-5);
+        assert_eq!(hit.name, "slider");
+        assert_eq!(hit.owner, ColliderOwner::Link(3));
+    }
+
+    #[test]
+    fn ray_misses_beyond_its_maximum_distance() {
+        let mut robot = robot_with_box();
+        let origin = Point::new(1.0, 0.0, 0.0);
+        assert_eq!(robot.cast_ray(origin, Vector::x(), 0.5).unwrap(), None);
+        assert_eq!(robot.cast_ray(origin, -Vector::y(), 10.0).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_rays() {
+        let mut robot = robot_with_box();
+        let origin = Point::origin();
+        assert!(robot.cast_ray(origin, Vector::zeros(), 1.0).is_err());
+        assert!(robot.cast_ray(origin, Vector::x(), -1.0).is_err());
+        assert!(robot
+            .cast_rays(&[(origin, Vector::x()), (origin, Vector::zeros())], 1.0)
+            .is_err());
+    }
+
+    #[test]
+    fn batch_matches_single_rays() {
+        let mut robot = robot_with_box();
+        let rays = [
+            (Point::new(1.0, 0.0, 0.0), Vector::x()),
+            (Point::new(1.0, 0.0, 0.0), -Vector::y()),
+            (Point::new(0.4, 0.0, 2.0), -Vector::z()),
+            (Point::new(3.0, 0.0, 0.0), -Vector::x()),
+        ];
+        let hits = robot.cast_rays(&rays, 10.0).unwrap();
+        assert_eq!(hits.len(), rays.len());
+        for ((origin, dir), hit) in rays.iter().zip(&hits) {
+            assert_eq!(hit, &robot.cast_ray(*origin, *dir, 10.0).unwrap());
+        }
+        assert_eq!(hits[0].as_ref().unwrap().name, "box");
+        assert!(hits[1].is_none());
+        assert_eq!(hits[2].as_ref().unwrap().name, "slider");
+        assert_eq!(hits[3].as_ref().unwrap().name, "box");
+    }
+}
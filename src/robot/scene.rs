@@ -0,0 +1,626 @@
+//! Several robots sharing one world.
+//!
+//! The scene has a single collision pipeline, holding a copy of the link (and attached
+//! object) colliders of every robot and one copy of each obstacle, so that a single
+//! broad phase finds the candidate pairs within and across robots. The copies follow
+//! their robot on every check, and pairs are filtered with the collision matrix of the
+//! robots they belong to.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use eyre::{ensure, OptionExt, Result};
+use rapier3d::math::{Isometry, Real};
+use rapier3d::prelude::{ColliderHandle, PairFilterContext, PhysicsHooks, SolverFlags};
+use urdf_rs::{Geometry, Pose};
+
+use super::{
+    geometry_to_colliders, CollisionResult, MeshColliderMode, Obstacle, Robot, RobotError,
+};
+use crate::collision_checker::{
+    ColliderBuilderActivateRobotLinkCollision, ColliderOwner, SimpleCollisionPipeline,
+};
+
+/// A robot of the scene, together with its per-robot settings.
+pub struct SceneRobot<K> {
+    pub key: K,
+    pub robot: Robot,
+    /// Whether collisions between links of this robot are reported.
+    pub check_self_collision: bool,
+    /// unique within the scene, and stored in the user data of the robot's colliders
+    id: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SceneCollisionResult<K> {
+    Free,
+    /// A robot is in self-collision, in collision with an obstacle, or violates its
+    /// joint limits.
+    Robot(K, CollisionResult),
+    /// Two robots are in collision with each other.
+    InterRobot(K, K),
+}
+
+impl<K> From<SceneCollisionResult<K>> for bool {
+    fn from(val: SceneCollisionResult<K>) -> Self {
+        !matches!(val, SceneCollisionResult::Free)
+    }
+}
+
+/// A pair of colliding links (or attached objects) that belong to different robots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterRobotContact<K> {
+    pub robot1: K,
+    pub name1: String,
+    pub robot2: K,
+    pub name2: String,
+}
+
+/// Several robots, each placed at its own base pose, that are checked against each other.
+/// Robots are identified by a key (e.g. a name), and the combined joint vector of the
+/// scene is the concatenation of the robots' joints in the order they were added.
+///
+/// Obstacles are shared by every robot and belong to the scene: obstacles added to a
+/// robot itself (see [`Robot::add_obstacle`]) are not part of the scene's checks.
+pub struct RobotScene<K = String> {
+    robots: Vec<SceneRobot<K>>,
+    next_robot_id: usize,
+    world: SimpleCollisionPipeline,
+    /// collider of the world for each (robot id, collider of the robot)
+    mirrors: HashMap<(usize, ColliderHandle), ColliderHandle>,
+    obstacles: HashMap<String, Obstacle>,
+}
+
+impl<K> Default for RobotScene<K> {
+    fn default() -> Self {
+        Self {
+            robots: Vec::new(),
+            next_robot_id: 0,
+            world: SimpleCollisionPipeline::default(),
+            mirrors: HashMap::new(),
+            obstacles: HashMap::new(),
+        }
+    }
+}
+
+/// Filters the pairs of the scene's world with the collision matrix of each robot.
+struct SceneFilter<'a, K> {
+    robots: &'a [SceneRobot<K>],
+}
+
+impl<K> SceneFilter<'_, K> {
+    fn robot(&self, id: usize) -> Option<&SceneRobot<K>> {
+        self.robots.iter().find(|entry| entry.id == id)
+    }
+
+    fn can_collide(&self, user_data1: u128, user_data2: u128) -> bool {
+        let owner1 = ColliderOwner::from_user_data(user_data1);
+        let owner2 = ColliderOwner::from_user_data(user_data2);
+        let robot1 = ColliderOwner::robot_from_user_data(user_data1);
+        let robot2 = ColliderOwner::robot_from_user_data(user_data2);
+        // whether a collider collides at all, i.e. is not of a disabled link
+        let is_enabled = |entry: &SceneRobot<K>, owner| {
+            entry
+                .robot
+                .collision_checker
+                .allowed_collisions
+                .can_collide(owner, ColliderOwner::Environment)
+        };
+
+        match (owner1, owner2) {
+            (ColliderOwner::Environment, ColliderOwner::Environment) => false,
+            (ColliderOwner::Environment, owner) => self
+                .robot(robot2)
+                .is_some_and(|entry| is_enabled(entry, owner)),
+            (owner, ColliderOwner::Environment) => self
+                .robot(robot1)
+                .is_some_and(|entry| is_enabled(entry, owner)),
+            _ if robot1 == robot2 => self.robot(robot1).is_some_and(|entry| {
+                entry.check_self_collision
+                    && entry
+                        .robot
+                        .collision_checker
+                        .allowed_collisions
+                        .can_collide(owner1, owner2)
+            }),
+            _ => match (self.robot(robot1), self.robot(robot2)) {
+                (Some(entry1), Some(entry2)) => {
+                    is_enabled(entry1, owner1) && is_enabled(entry2, owner2)
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+impl<K: Sync> PhysicsHooks for SceneFilter<'_, K> {
+    fn filter_contact_pair(&self, context: &PairFilterContext) -> Option<SolverFlags> {
+        self.can_collide(
+            context.colliders[context.collider1].user_data,
+            context.colliders[context.collider2].user_data,
+        )
+        .then_some(SolverFlags::empty())
+    }
+}
+
+fn remove_world_collider(world: &mut SimpleCollisionPipeline, handle: ColliderHandle) {
+    world.collider_set.remove(
+        handle,
+        &mut world.island_manager,
+        &mut world.rigid_body_set,
+        false,
+    );
+}
+
+/// Moves the robot's colliders to its current joint positions, and makes the world's
+/// copies match them: colliders added to the robot (e.g. attached objects) are copied,
+/// and the copies of removed ones are removed.
+fn sync_robot<K>(
+    world: &mut SimpleCollisionPipeline,
+    mirrors: &mut HashMap<(usize, ColliderHandle), ColliderHandle>,
+    entry: &mut SceneRobot<K>,
+) -> Result<()> {
+    entry.robot.update_link_colliders()?;
+
+    let mut alive = HashSet::new();
+    for (handle, collider) in entry.robot.collision_checker.collider_set.iter() {
+        let owner = ColliderOwner::from_user_data(collider.user_data);
+        if owner == ColliderOwner::Environment {
+            continue;
+        }
+        alive.insert(handle);
+        match mirrors.get(&(entry.id, handle)) {
+            Some(mirror) => {
+                let mirror = world
+                    .collider_set
+                    .get_mut(*mirror)
+                    .ok_or_eyre("cannot find collider")?;
+                mirror.set_position(*collider.position());
+                // the padding of the robot may have changed
+                mirror.set_contact_skin(collider.contact_skin());
+            }
+            None => {
+                let mut mirror = collider.clone();
+                mirror.user_data = owner.to_user_data_of_robot(entry.id);
+                mirrors.insert((entry.id, handle), world.collider_set.insert(mirror));
+            }
+        }
+    }
+
+    let removed: Vec<_> = mirrors
+        .keys()
+        .filter(|(id, handle)| *id == entry.id && !alive.contains(handle))
+        .copied()
+        .collect();
+    for key in removed {
+        if let Some(mirror) = mirrors.remove(&key) {
+            remove_world_collider(world, mirror);
+        }
+    }
+    Ok(())
+}
+
+/// Name of the link or attached object of a robot's collider.
+fn owner_name(robot: &Robot, owner: ColliderOwner) -> Option<&str> {
+    match owner {
+        ColliderOwner::Link(idx) => robot.urdf_robot.links.get(idx).map(|l| l.name.as_str()),
+        ColliderOwner::Attached(id) => robot
+            .attached_objects
+            .iter()
+            .find(|(_, object)| object.id == id)
+            .map(|(name, _)| name.as_str()),
+        ColliderOwner::Environment => None,
+    }
+}
+
+impl<K: Clone + Eq + Hash + Debug + Sync> RobotScene<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn robots(&self) -> impl Iterator<Item = &SceneRobot<K>> {
+        self.robots.iter()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.robots.iter().any(|entry| &entry.key == key)
+    }
+
+    fn entry(&self, key: &K) -> Result<&SceneRobot<K>> {
+        self.robots
+            .iter()
+            .find(|entry| &entry.key == key)
+            .ok_or_else(|| RobotError::UnknownRobot(format!("{:?}", key)).into())
+    }
+
+    fn entry_mut(&mut self, key: &K) -> Result<&mut SceneRobot<K>> {
+        self.robots
+            .iter_mut()
+            .find(|entry| &entry.key == key)
+            .ok_or_else(|| RobotError::UnknownRobot(format!("{:?}", key)).into())
+    }
+
+    pub fn robot(&self, key: &K) -> Result<&Robot> {
+        self.entry(key).map(|entry| &entry.robot)
+    }
+
+    pub fn robot_mut(&mut self, key: &K) -> Result<&mut Robot> {
+        self.entry_mut(key).map(|entry| &mut entry.robot)
+    }
+
+    /// Adds a robot at the given base pose. Keys must be unique within the scene.
+    pub fn add_robot(&mut self, key: K, mut robot: Robot, base_pose: Isometry<Real>) -> Result<()> {
+        ensure!(
+            !self.contains(&key),
+            "A robot {:?} is already in the scene",
+            key
+        );
+        robot.set_base_pose(base_pose);
+        // its colliders are copied into the world on the next check
+        self.robots.push(SceneRobot {
+            key,
+            robot,
+            check_self_collision: true,
+            id: self.next_robot_id,
+        });
+        self.next_robot_id += 1;
+        Ok(())
+    }
+
+    pub fn remove_robot(&mut self, key: &K) -> Result<Robot> {
+        let idx = self
+            .robots
+            .iter()
+            .position(|entry| &entry.key == key)
+            .ok_or_else(|| RobotError::UnknownRobot(format!("{:?}", key)))?;
+        let entry = self.robots.remove(idx);
+
+        let world = &mut self.world;
+        self.mirrors.retain(|(id, _), mirror| {
+            if *id == entry.id {
+                remove_world_collider(world, *mirror);
+            }
+            *id != entry.id
+        });
+        Ok(entry.robot)
+    }
+
+    pub fn set_base_pose(&mut self, key: &K, pose: Isometry<Real>) -> Result<()> {
+        self.robot_mut(key)?.set_base_pose(pose);
+        Ok(())
+    }
+
+    /// Whether collisions between the links of a robot are checked. Collisions with
+    /// obstacles and with other robots are always checked.
+    pub fn set_self_collision(&mut self, key: &K, enabled: bool) -> Result<()> {
+        self.entry_mut(key)?.check_self_collision = enabled;
+        Ok(())
+    }
+
+    /// Adds a static obstacle shared by every robot of the scene, replacing any existing
+    /// obstacle with the same name.
+    ///
+    /// Relative mesh filenames are resolved against the current working directory.
+    pub fn add_obstacle(
+        &mut self,
+        name: &str,
+        geometry: &Geometry,
+        pose: Isometry<Real>,
+    ) -> Result<()> {
+        let mut builders = geometry_to_colliders(
+            &None,
+            geometry,
+            &Pose::default(),
+            MeshColliderMode::default(),
+        );
+        ensure!(
+            !builders.is_empty(),
+            "Obstacle '{}' has no collision shape (failed to load its mesh?)",
+            name
+        );
+
+        if self.obstacles.contains_key(name) {
+            self.remove_obstacle(name)?;
+        }
+
+        let colliders = builders
+            .drain(..)
+            .map(|builder| {
+                let local_pose = builder.position;
+                let collider = builder
+                    .position(pose * local_pose)
+                    .activate_as_environment_obstacle()
+                    .build();
+                (self.world.collider_set.insert(collider), local_pose)
+            })
+            .collect();
+        self.obstacles.insert(
+            name.to_owned(),
+            Obstacle {
+                pose,
+                colliders,
+                voxel_grid: None,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn set_obstacle_pose(&mut self, name: &str, pose: Isometry<Real>) -> Result<()> {
+        let obstacle = self
+            .obstacles
+            .get_mut(name)
+            .ok_or_else(|| RobotError::UnknownObstacle(name.to_owned()))?;
+        obstacle.pose = pose;
+        for (handle, local_pose) in &obstacle.colliders {
+            self.world
+                .collider_set
+                .get_mut(*handle)
+                .ok_or_eyre("cannot find collider")?
+                .set_position(pose * local_pose);
+        }
+        Ok(())
+    }
+
+    pub fn remove_obstacle(&mut self, name: &str) -> Result<()> {
+        let obstacle = self
+            .obstacles
+            .remove(name)
+            .ok_or_else(|| RobotError::UnknownObstacle(name.to_owned()))?;
+        for (handle, _) in obstacle.colliders {
+            remove_world_collider(&mut self.world, handle);
+        }
+        Ok(())
+    }
+
+    /// Total number of joints of all robots.
+    pub fn dof(&self) -> usize {
+        self.robots.iter().map(|entry| entry.robot.dof()).sum()
+    }
+
+    /// The combined joint vector of the scene.
+    pub fn joints(&self) -> Vec<f32> {
+        self.robots
+            .iter()
+            .flat_map(|entry| entry.robot.robot_chain.joint_positions())
+            .collect()
+    }
+
+    /// Sets the joints of every robot from the combined joint vector.
+    pub fn set_joints(&mut self, joints: &[f32]) -> Result<()> {
+        ensure!(
+            joints.len() == self.dof(),
+            "Expected {} joint values for the scene, got {}",
+            self.dof(),
+            joints.len()
+        );
+        let mut offset = 0;
+        for entry in &mut self.robots {
+            let dof = entry.robot.dof();
+            entry.robot.set_joints(&joints[offset..offset + dof])?;
+            offset += dof;
+        }
+        Ok(())
+    }
+
+    /// Sets the joints from the combined joint vector and checks the scene for collisions,
+    /// reporting joint limit violations as [`CollisionResult::JointLimitViolation`].
+    pub fn collision_at(&mut self, joints: &[f32]) -> Result<SceneCollisionResult<K>> {
+        ensure!(
+            joints.len() == self.dof(),
+            "Expected {} joint values for the scene, got {}",
+            self.dof(),
+            joints.len()
+        );
+        let mut offset = 0;
+        for entry in &mut self.robots {
+            let dof = entry.robot.dof();
+            if let Err(err) = entry.robot.set_joints(&joints[offset..offset + dof]) {
+                return match err.downcast::<RobotError>() {
+                    Ok(RobotError::SetJointLimitViolation) => Ok(SceneCollisionResult::Robot(
+                        entry.key.clone(),
+                        CollisionResult::JointLimitViolation,
+                    )),
+                    Ok(e) => Err(e.into()),
+                    Err(e) => Err(e),
+                };
+            }
+            offset += dof;
+        }
+        self.has_collision()
+    }
+
+    /// Checks every robot against the obstacles, itself (if enabled) and the other robots,
+    /// in one pass over the scene's world. Collisions with obstacles take precedence over
+    /// self-collisions, which take precedence over collisions between robots.
+    pub fn has_collision(&mut self) -> Result<SceneCollisionResult<K>> {
+        for entry in &mut self.robots {
+            sync_robot(&mut self.world, &mut self.mirrors, entry)?;
+        }
+        self.world.update_with_hooks(&SceneFilter {
+            robots: &self.robots,
+        });
+
+        let mut self_collision = None;
+        let mut inter_robot = None;
+        for (handle1, handle2) in self.world.colliding_pairs() {
+            let (user_data1, user_data2) = self.user_data_pair(handle1, handle2)?;
+            let (owner1, owner2) = (
+                ColliderOwner::from_user_data(user_data1),
+                ColliderOwner::from_user_data(user_data2),
+            );
+            let (robot1, robot2) = (
+                ColliderOwner::robot_from_user_data(user_data1),
+                ColliderOwner::robot_from_user_data(user_data2),
+            );
+
+            if owner1 == ColliderOwner::Environment || owner2 == ColliderOwner::Environment {
+                let robot = if owner1 == ColliderOwner::Environment {
+                    robot2
+                } else {
+                    robot1
+                };
+                return Ok(SceneCollisionResult::Robot(
+                    self.key_of(robot)?,
+                    CollisionResult::EnvironmentCollision,
+                ));
+            } else if robot1 == robot2 {
+                self_collision.get_or_insert(robot1);
+            } else {
+                inter_robot.get_or_insert((robot1, robot2));
+            }
+        }
+
+        Ok(match (self_collision, inter_robot) {
+            (Some(robot), _) => {
+                SceneCollisionResult::Robot(self.key_of(robot)?, CollisionResult::SelfCollision)
+            }
+            (None, Some((robot1, robot2))) => {
+                SceneCollisionResult::InterRobot(self.key_of(robot1)?, self.key_of(robot2)?)
+            }
+            (None, None) => SceneCollisionResult::Free,
+        })
+    }
+
+    /// Colliding pairs of links (or attached objects) from different robots, as of the
+    /// last call to [`RobotScene::has_collision`].
+    pub fn inter_robot_colliding_pairs(&self) -> Result<Vec<InterRobotContact<K>>> {
+        let mut contacts = Vec::new();
+        for (handle1, handle2) in self.world.colliding_pairs() {
+            let (user_data1, user_data2) = self.user_data_pair(handle1, handle2)?;
+            let (owner1, owner2) = (
+                ColliderOwner::from_user_data(user_data1),
+                ColliderOwner::from_user_data(user_data2),
+            );
+            let (robot1, robot2) = (
+                ColliderOwner::robot_from_user_data(user_data1),
+                ColliderOwner::robot_from_user_data(user_data2),
+            );
+            if owner1 == ColliderOwner::Environment
+                || owner2 == ColliderOwner::Environment
+                || robot1 == robot2
+            {
+                continue;
+            }
+
+            let (entry1, entry2) = (self.entry_of(robot1)?, self.entry_of(robot2)?);
+            contacts.push(InterRobotContact {
+                robot1: entry1.key.clone(),
+                name1: owner_name(&entry1.robot, owner1)
+                    .ok_or_eyre("cannot find the link of a collider")?
+                    .to_owned(),
+                robot2: entry2.key.clone(),
+                name2: owner_name(&entry2.robot, owner2)
+                    .ok_or_eyre("cannot find the link of a collider")?
+                    .to_owned(),
+            });
+        }
+        Ok(contacts)
+    }
+
+    fn user_data_pair(
+        &self,
+        handle1: ColliderHandle,
+        handle2: ColliderHandle,
+    ) -> Result<(u128, u128)> {
+        let user_data = |handle| {
+            self.world
+                .collider_set
+                .get(handle)
+                .map(|collider| collider.user_data)
+                .ok_or_eyre("cannot find collider")
+        };
+        Ok((user_data(handle1)?, user_data(handle2)?))
+    }
+
+    fn entry_of(&self, id: usize) -> Result<&SceneRobot<K>> {
+        self.robots
+            .iter()
+            .find(|entry| entry.id == id)
+            .ok_or_eyre("cannot find the robot of a collider")
+    }
+
+    fn key_of(&self, id: usize) -> Result<K> {
+        self.entry_of(id).map(|entry| entry.key.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use rapier3d::math::Vector;
+
+    use super::*;
+    use crate::robot::test_util::arm;
+
+    /// Two arms facing each other, with their sliders 0.1 apart at zero.
+    fn facing_arms() -> RobotScene<String> {
+        let mut scene = RobotScene::new();
+        scene
+            .add_robot("a".to_owned(), arm(), Isometry::identity())
+            .unwrap();
+        let facing = Isometry::new(Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 0.0, PI));
+        scene.add_robot("b".to_owned(), arm(), facing).unwrap();
+        scene
+    }
+
+    #[test]
+    fn robots_collide_with_each_other() {
+        let mut scene = facing_arms();
+        assert_eq!(scene.dof(), 6);
+        assert_eq!(scene.has_collision().unwrap(), SceneCollisionResult::Free);
+        assert!(scene
+            .add_robot("a".to_owned(), arm(), Isometry::identity())
+            .is_err());
+
+        // extending a's slider puts it on top of b's
+        let result = scene.collision_at(&[0.0, 0.0, 0.2, 0.0, 0.0, 0.0]).unwrap();
+        assert!(matches!(result, SceneCollisionResult::InterRobot(..)));
+        let contacts = scene.inter_robot_colliding_pairs().unwrap();
+        assert!(contacts.iter().any(|contact| {
+            contact.robot1 != contact.robot2
+                && contact.name1 == "slider"
+                && contact.name2 == "slider"
+        }));
+        assert!(contacts
+            .iter()
+            .all(|contact| contact.robot1 != contact.robot2));
+    }
+
+    #[test]
+    fn robots_are_placed_at_their_base_pose() {
+        let mut scene = facing_arms();
+        scene.set_joints(&[0.0, 0.0, 0.2, 0.0, 0.0, 0.0]).unwrap();
+        assert!(matches!(
+            scene.has_collision().unwrap(),
+            SceneCollisionResult::InterRobot(..)
+        ));
+
+        let away = Isometry::new(Vector::new(3.0, 0.0, 0.0), Vector::new(0.0, 0.0, PI));
+        scene.set_base_pose(&"b".to_owned(), away).unwrap();
+        assert_eq!(scene.has_collision().unwrap(), SceneCollisionResult::Free);
+        assert!(scene.inter_robot_colliding_pairs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn removed_robots_leave_the_world() {
+        let mut scene = facing_arms();
+        scene.set_joints(&[0.0, 0.0, 0.2, 0.0, 0.0, 0.0]).unwrap();
+        assert!(bool::from(scene.has_collision().unwrap()));
+        assert_eq!(scene.world.collider_set.len(), 2 * 4);
+
+        let robot = scene.remove_robot(&"b".to_owned()).unwrap();
+        assert_eq!(robot.dof(), 3);
+        assert!(!scene.contains(&"b".to_owned()));
+        assert_eq!(scene.dof(), 3);
+        assert_eq!(scene.world.collider_set.len(), 4);
+        assert_eq!(scene.has_collision().unwrap(), SceneCollisionResult::Free);
+
+        let err = scene.remove_robot(&"b".to_owned()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RobotError>(),
+            Some(RobotError::UnknownRobot(_))
+        ));
+    }
+}
@@ -0,0 +1,172 @@
+//! Process-wide cache of the collision shapes built from mesh files.
+//!
+//! Loading and converting a large mesh is slow, and the same meshes are typically loaded
+//! by every robot instance (e.g. one per worker or per entity). Shapes are immutable and
+//! reference counted, so robots loading the same mesh simply share them.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use log::warn;
+use rapier3d::math::{Point, Real, Vector};
+use rapier3d::prelude::SharedShape;
+
+use super::MeshColliderMode;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ShapeCacheKey {
+    path: PathBuf,
+    /// bit patterns of the scale, as floats are not hashable
+    scale: [u32; 3],
+    mode: MeshColliderMode,
+}
+
+type ShapeCache = Mutex<HashMap<ShapeCacheKey, Vec<SharedShape>>>;
+
+fn shape_cache() -> &'static ShapeCache {
+    static CACHE: OnceLock<ShapeCache> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// Drops every cached shape, e.g. after mesh files have been modified on disk. Robots
+/// that have already been created keep their shapes.
+pub fn clear_mesh_shape_cache() {
+    shape_cache()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clear();
+}
+
+/// The shapes (one per mesh within the file) of the mesh file at `path`, scaled and
+/// converted with `mode`. Meshes that fail to load are reported and not cached.
+pub(crate) fn mesh_shapes(
+    path: &str,
+    scale: Vector<Real>,
+    mode: MeshColliderMode,
+) -> Vec<SharedShape> {
+    let key = ShapeCacheKey {
+        path: std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path)),
+        scale: [scale.x.to_bits(), scale.y.to_bits(), scale.z.to_bits()],
+        mode,
+    };
+
+    if let Some(shapes) = shape_cache()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(&key)
+    {
+        return shapes.clone();
+    }
+
+    // the lock is not held while loading, so that different meshes load concurrently
+    let Some(shapes) = load_mesh_shapes(path, scale, mode) else {
+        return Vec::new();
+    };
+    shape_cache()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(key, shapes.clone());
+    shapes
+}
+
+fn load_mesh_shapes(
+    path: &str,
+    scale: Vector<Real>,
+    mode: MeshColliderMode,
+) -> Option<Vec<SharedShape>> {
+    // a mirroring scale turns the triangles inside out
+    let flip_winding = scale.x * scale.y * scale.z < 0.0;
+
+    let scene = match mesh_loader::Loader::default().load(path) {
+        Ok(scene) => scene,
+        Err(e) => {
+            warn!("Failed to load mesh {}: {}", path, e);
+            return None;
+        }
+    };
+
+    let mut shapes = Vec::new();
+    for raw_mesh in scene.meshes {
+        let vertices: Vec<_> = raw_mesh
+            .vertices
+            .iter()
+            .map(|v| Point::new(v[0] * scale.x, v[1] * scale.y, v[2] * scale.z))
+            .collect();
+        let mut indices: Vec<_> = raw_mesh.faces;
+        if flip_winding {
+            indices.iter_mut().for_each(|face| face.swap(1, 2));
+        }
+        // none of the converters that we use move the shape
+        match mode.converter().convert(vertices, indices) {
+            Ok((shape, _)) => shapes.push(shape),
+            Err(e) => warn!("Failed to convert mesh {}: {}", path, e),
+        }
+    }
+    Some(shapes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rapier3d::parry::query::PointQuery;
+
+    use super::*;
+    use crate::robot::test_util::cube_stl;
+
+    #[test]
+    fn meshes_are_scaled() {
+        let path = cube_stl("scaled");
+        let shapes = mesh_shapes(&path, Vector::new(2.0, 1.0, 0.5), MeshColliderMode::TriMesh);
+        assert_eq!(shapes.len(), 1);
+        let aabb = shapes[0].compute_local_aabb();
+        assert!((aabb.mins - Point::new(-1.0, -0.5, -0.25)).norm() < 1e-5);
+        assert!((aabb.maxs - Point::new(1.0, 0.5, 0.25)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn mirrored_meshes_face_outwards() {
+        let path = cube_stl("mirrored");
+        for scale in [Vector::new(1.0, 1.0, 1.0), Vector::new(-1.0, 1.0, 1.0)] {
+            let shapes = mesh_shapes(&path, scale, MeshColliderMode::TriMesh);
+            let trimesh = shapes[0].as_trimesh().unwrap();
+            assert_eq!(trimesh.num_triangles(), 12);
+            for triangle in trimesh.triangles() {
+                let normal = triangle.normal().unwrap();
+                assert!(normal.dot(&triangle.center().coords) > 0.0);
+            }
+            assert!(shapes[0].contains_local_point(&Point::origin()));
+        }
+    }
+
+    #[test]
+    fn missing_meshes_have_no_shapes() {
+        let shapes = mesh_shapes(
+            "/nonexistent/mesh.stl",
+            Vector::repeat(1.0),
+            MeshColliderMode::TriMesh,
+        );
+        assert!(shapes.is_empty());
+    }
+
+    #[test]
+    fn shapes_are_shared_until_the_cache_is_cleared() {
+        let path = cube_stl("cached");
+        let load = |scale| mesh_shapes(&path, scale, MeshColliderMode::ConvexHull);
+        let (unit, doubled) = (Vector::repeat(1.0), Vector::repeat(2.0));
+
+        let shapes = load(unit);
+        assert!(Arc::ptr_eq(&shapes[0].0, &load(unit)[0].0));
+        assert!(!Arc::ptr_eq(&shapes[0].0, &load(doubled)[0].0));
+        assert!(!Arc::ptr_eq(
+            &shapes[0].0,
+            &mesh_shapes(&path, unit, MeshColliderMode::TriMesh)[0].0
+        ));
+
+        clear_mesh_shape_cache();
+        let reloaded = load(unit);
+        assert!(!Arc::ptr_eq(&shapes[0].0, &reloaded[0].0));
+        assert!(Arc::ptr_eq(&reloaded[0].0, &load(unit)[0].0));
+    }
+}
@@ -0,0 +1,371 @@
+//! Parsing of MoveIt's Semantic Robot Description Format (SRDF).
+//!
+//! Only the elements that we make use of are parsed; everything else (e.g.
+//! `virtual_joint`, `passive_joint`, `link_sphere_approximation`) is ignored.
+
+use std::path::Path;
+
+use eyre::{Context, Result};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Srdf {
+    #[serde(rename = "@name", default)]
+    pub name: String,
+    #[serde(rename = "group", default)]
+    pub groups: Vec<PlanningGroup>,
+    #[serde(rename = "group_state", default)]
+    pub group_states: Vec<GroupState>,
+    #[serde(rename = "end_effector", default)]
+    pub end_effectors: Vec<EndEffector>,
+    #[serde(rename = "disable_collisions", default)]
+    pub disable_collisions: Vec<DisableCollisions>,
+}
+
+/// An element that only carries a `name` attribute, e.g. `<joint name="..."/>` within a group.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct NamedElement {
+    #[serde(rename = "@name")]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GroupChain {
+    #[serde(rename = "@base_link")]
+    pub base_link: String,
+    #[serde(rename = "@tip_link")]
+    pub tip_link: String,
+}
+
+/// A `<group>`, which can be defined by chains, joints, links and other groups.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PlanningGroup {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "chain", default)]
+    pub chains: Vec<GroupChain>,
+    #[serde(rename = "joint", default)]
+    pub joints: Vec<NamedElement>,
+    #[serde(rename = "link", default)]
+    pub links: Vec<NamedElement>,
+    #[serde(rename = "group", default)]
+    pub subgroups: Vec<NamedElement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GroupStateJoint {
+    #[serde(rename = "@name")]
+    pub name: String,
+    /// Space separated joint values (multi-dof joints have more than one value).
+    #[serde(rename = "@value")]
+    pub value: String,
+}
+
+/// A named configuration (`<group_state>`) of a planning group.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GroupState {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@group")]
+    pub group: String,
+    #[serde(rename = "joint", default)]
+    pub joints: Vec<GroupStateJoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct EndEffector {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@group")]
+    pub group: String,
+    #[serde(rename = "@parent_link")]
+    pub parent_link: String,
+    #[serde(rename = "@parent_group", default)]
+    pub parent_group: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DisableCollisions {
+    #[serde(rename = "@link1")]
+    pub link1: String,
+    #[serde(rename = "@link2")]
+    pub link2: String,
+    #[serde(rename = "@reason", default)]
+    pub reason: Option<String>,
+}
+
+impl Srdf {
+    pub fn group(&self, name: &str) -> Option<&PlanningGroup> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    pub fn group_state(&self, group: &str, name: &str) -> Option<&GroupState> {
+        self.group_states
+            .iter()
+            .find(|state| state.group == group && state.name == name)
+    }
+
+    pub fn end_effector(&self, name: &str) -> Option<&EndEffector> {
+        self.end_effectors.iter().find(|ee| ee.name == name)
+    }
+}
+
+impl GroupState {
+    /// Joint positions of this state. Only single-dof joints are supported.
+    pub fn joint_positions(&self) -> Result<Vec<(String, f32)>> {
+        self.joints
+            .iter()
+            .map(|joint| {
+                let value = joint.value.trim().parse::<f32>().wrap_err_with(|| {
+                    format!(
+                        "Invalid value '{}' for joint '{}' in group state '{}'",
+                        joint.value, joint.name, self.name
+                    )
+                })?;
+                Ok((joint.name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+/// Formats the pairs as srdf `<disable_collisions>` elements, one per line, ready to be
+/// pasted into an srdf file.
+pub fn disable_collisions_to_string(pairs: &[DisableCollisions]) -> String {
+    use quick_xml::escape::escape;
+
+    pairs
+        .iter()
+        .map(|pair| match &pair.reason {
+            Some(reason) => format!(
+                r#"<disable_collisions link1="{}" link2="{}" reason="{}" />"#,
+                escape(&pair.link1),
+                escape(&pair.link2),
+                escape(reason)
+            ),
+            None => format!(
+                r#"<disable_collisions link1="{}" link2="{}" />"#,
+                escape(&pair.link1),
+                escape(&pair.link2)
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Formats the pairs as a complete srdf document for the robot, holding only the
+/// `<disable_collisions>` elements.
+pub fn disable_collisions_to_srdf(robot_name: &str, pairs: &[DisableCollisions]) -> String {
+    let elements = disable_collisions_to_string(pairs);
+    let body = if elements.is_empty() {
+        String::new()
+    } else {
+        elements
+            .lines()
+            .map(|line| format!("  {}\n", line))
+            .collect()
+    };
+    format!(
+        "<?xml version=\"1.0\"?>\n<robot name=\"{}\">\n{}</robot>\n",
+        quick_xml::escape::escape(robot_name),
+        body
+    )
+}
+
+pub fn read_from_string(string: &str) -> Result<Srdf> {
+    quick_xml::de::from_str(string).wrap_err("Failed to parse srdf")
+}
+
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Srdf> {
+    let path = path.as_ref();
+    let string = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read srdf file {}", path.display()))?;
+    read_from_string(&string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::{Robot, RobotError};
+
+    const SRDF: &str = r#"<?xml version="1.0"?>
+<robot name="arm">
+  <virtual_joint name="world_joint" type="fixed" parent_frame="world" child_link="base" />
+  <group name="arm">
+    <chain base_link="base" tip_link="link2" />
+  </group>
+  <group name="hand">
+    <joint name="finger_joint" />
+    <link name="finger" />
+  </group>
+  <group name="all">
+    <group name="arm" />
+    <group name="hand" />
+  </group>
+  <group_state name="home" group="arm">
+    <joint name="joint1" value="0.5" />
+    <joint name="joint2" value=" -1 " />
+  </group_state>
+  <end_effector name="gripper" group="hand" parent_link="link2" />
+  <disable_collisions link1="base" link2="link1" reason="Adjacent" />
+  <disable_collisions link1="link1" link2="link2" />
+</robot>"#;
+
+    const URDF: &str = r#"<robot name="arm">
+  <link name="base" />
+  <link name="link1" />
+  <link name="link2" />
+  <link name="finger" />
+  <joint name="joint1" type="revolute">
+    <parent link="base" />
+    <child link="link1" />
+    <axis xyz="0 0 1" />
+    <limit lower="-3" upper="3" effort="0" velocity="0" />
+  </joint>
+  <joint name="joint2" type="revolute">
+    <parent link="link1" />
+    <child link="link2" />
+    <axis xyz="0 1 0" />
+    <limit lower="-3" upper="3" effort="0" velocity="0" />
+  </joint>
+  <joint name="finger_joint" type="prismatic">
+    <parent link="link2" />
+    <child link="finger" />
+    <axis xyz="1 0 0" />
+    <limit lower="0" upper="0.1" effort="0" velocity="0" />
+  </joint>
+</robot>"#;
+
+    fn robot() -> Robot {
+        let mut robot =
+            Robot::from_urdf_robot(urdf_rs::read_from_string(URDF).unwrap(), None).unwrap();
+        robot.apply_srdf(read_from_string(SRDF).unwrap());
+        robot
+    }
+
+    #[test]
+    fn parses_srdf() {
+        let srdf = read_from_string(SRDF).unwrap();
+        assert_eq!(srdf.name, "arm");
+        assert_eq!(srdf.groups.len(), 3);
+        assert_eq!(
+            srdf.group("arm").unwrap().chains,
+            vec![GroupChain {
+                base_link: "base".to_owned(),
+                tip_link: "link2".to_owned(),
+            }]
+        );
+        assert_eq!(srdf.group("all").unwrap().subgroups.len(), 2);
+        assert!(srdf.group("missing").is_none());
+
+        let home = srdf.group_state("arm", "home").unwrap();
+        assert_eq!(
+            home.joint_positions().unwrap(),
+            vec![("joint1".to_owned(), 0.5), ("joint2".to_owned(), -1.0)]
+        );
+        assert!(srdf.group_state("hand", "home").is_none());
+
+        let gripper = srdf.end_effector("gripper").unwrap();
+        assert_eq!(gripper.parent_link, "link2");
+        assert_eq!(gripper.parent_group, None);
+
+        assert_eq!(srdf.disable_collisions.len(), 2);
+        assert_eq!(
+            srdf.disable_collisions[0].reason.as_deref(),
+            Some("Adjacent")
+        );
+        assert_eq!(srdf.disable_collisions[1].reason, None);
+    }
+
+    #[test]
+    fn writes_disable_collisions() {
+        let srdf = read_from_string(SRDF).unwrap();
+        assert_eq!(
+            disable_collisions_to_string(&srdf.disable_collisions),
+            "<disable_collisions link1=\"base\" link2=\"link1\" reason=\"Adjacent\" />\n\
+             <disable_collisions link1=\"link1\" link2=\"link2\" />"
+        );
+    }
+
+    #[test]
+    fn writes_srdf_document() {
+        let srdf = read_from_string(SRDF).unwrap();
+        let document = disable_collisions_to_srdf("arm", &srdf.disable_collisions);
+        let parsed = read_from_string(&document).unwrap();
+        assert_eq!(parsed.name, "arm");
+        assert_eq!(parsed.disable_collisions, srdf.disable_collisions);
+
+        let empty = read_from_string(&disable_collisions_to_srdf("arm", &[])).unwrap();
+        assert!(empty.disable_collisions.is_empty());
+    }
+
+    #[test]
+    fn resolves_group_joints() {
+        let robot = robot();
+        assert_eq!(
+            robot.group_joint_names("arm").unwrap(),
+            vec!["joint1", "joint2"]
+        );
+        assert_eq!(
+            robot.group_joint_names("all").unwrap(),
+            vec!["joint1", "joint2", "finger_joint"]
+        );
+    }
+
+    #[test]
+    fn rejects_chain_with_tip_above_base() {
+        let mut robot = robot();
+        robot.srdf.groups.push(PlanningGroup {
+            name: "reversed".to_owned(),
+            chains: vec![GroupChain {
+                base_link: "link2".to_owned(),
+                tip_link: "link1".to_owned(),
+            }],
+            joints: Vec::new(),
+            links: Vec::new(),
+            subgroups: Vec::new(),
+        });
+
+        let err = robot.group_joint_names("reversed").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RobotError>(),
+            Some(RobotError::NotADescendant { tip_link, base_link })
+                if tip_link == "link1" && base_link == "link2"
+        ));
+    }
+
+    #[test]
+    fn rejects_cyclic_subgroups() {
+        let mut robot = robot();
+        let group = |name: &str, subgroups: &[&str]| PlanningGroup {
+            name: name.to_owned(),
+            chains: Vec::new(),
+            joints: vec![NamedElement {
+                name: format!("{}_joint", name),
+            }],
+            links: Vec::new(),
+            subgroups: subgroups
+                .iter()
+                .map(|subgroup| NamedElement {
+                    name: subgroup.to_string(),
+                })
+                .collect(),
+        };
+        robot.srdf.groups.push(group("a", &["b"]));
+        robot.srdf.groups.push(group("b", &["a"]));
+        robot.srdf.groups.push(group("c", &["a"]));
+        robot.srdf.groups.push(group("d", &["arm", "all"]));
+
+        for name in ["a", "c"] {
+            let err = robot.group_joint_names(name).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<RobotError>(),
+                Some(RobotError::CyclicPlanningGroup(group)) if group == "a"
+            ));
+        }
+        // a group reached through two paths is not a cycle
+        assert_eq!(
+            robot.group_joint_names("d").unwrap(),
+            vec!["d_joint", "joint1", "joint2", "finger_joint"]
+        );
+    }
+}
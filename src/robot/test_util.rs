@@ -0,0 +1,131 @@
+//! Robots and obstacles shared by the unit tests.
+
+use rapier3d::math::{Isometry, Real};
+use urdf_rs::Geometry;
+
+use super::Robot;
+
+/// An arm with a vertical revolute joint, a horizontal revolute joint and a prismatic joint,
+/// with one primitive collider per link. At zero, `link2` points along x at a height of 0.6,
+/// and the `slider` ball (radius 0.05) is centered at (0.4, 0, 0.6). Nothing is in contact
+/// there, apart from adjacent links.
+pub(crate) const ARM_URDF: &str = r#"<robot name="arm">
+  <link name="base">
+    <collision>
+      <origin xyz="0 0 0.05" />
+      <geometry><box size="0.2 0.2 0.1" /></geometry>
+    </collision>
+  </link>
+  <link name="link1">
+    <collision>
+      <origin xyz="0 0 0.25" />
+      <geometry><box size="0.1 0.1 0.5" /></geometry>
+    </collision>
+  </link>
+  <link name="link2">
+    <collision>
+      <origin xyz="0.2 0 0" />
+      <geometry><box size="0.4 0.06 0.06" /></geometry>
+    </collision>
+  </link>
+  <link name="slider">
+    <collision>
+      <geometry><sphere radius="0.05" /></geometry>
+    </collision>
+  </link>
+  <joint name="joint1" type="revolute">
+    <parent link="base" />
+    <child link="link1" />
+    <origin xyz="0 0 0.1" />
+    <axis xyz="0 0 1" />
+    <limit lower="-3" upper="3" effort="0" velocity="0" />
+  </joint>
+  <joint name="joint2" type="revolute">
+    <parent link="link1" />
+    <child link="link2" />
+    <origin xyz="0 0 0.5" />
+    <axis xyz="0 1 0" />
+    <limit lower="-1.5" upper="1.5" effort="0" velocity="0" />
+  </joint>
+  <joint name="joint3" type="prismatic">
+    <parent link="link2" />
+    <child link="slider" />
+    <origin xyz="0.4 0 0" />
+    <axis xyz="1 0 0" />
+    <limit lower="0" upper="1" effort="0" velocity="0" />
+  </joint>
+</robot>"#;
+
+/// Joints of [`arm`] for which `link2` points down, and the slider is inside `link1` (and
+/// touches nothing else).
+pub(crate) const SELF_COLLIDING: [f32; 3] = [0.0, 1.5, 0.0];
+
+pub(crate) fn arm() -> Robot {
+    Robot::from_urdf_robot(urdf_rs::read_from_string(ARM_URDF).unwrap(), None).unwrap()
+}
+
+/// Position of the slider of [`arm`], which is at a distance of `0.4 + q[2]` from the
+/// `joint2` axis.
+pub(crate) fn slider_position(q: &[f32]) -> [Real; 3] {
+    let r = 0.4 + q[2];
+    [
+        r * q[1].cos() * q[0].cos(),
+        r * q[1].cos() * q[0].sin(),
+        0.6 - r * q[1].sin(),
+    ]
+}
+
+pub(crate) fn ball(radius: f64) -> Geometry {
+    Geometry::Sphere { radius }
+}
+
+pub(crate) fn cuboid(x: f64, y: f64, z: f64) -> Geometry {
+    Geometry::Box {
+        size: urdf_rs::Vec3([x, y, z]),
+    }
+}
+
+pub(crate) fn at([x, y, z]: [Real; 3]) -> Isometry<Real> {
+    Isometry::translation(x, y, z)
+}
+
+/// Writes an ascii STL of a cube of side 1, centered at the origin and with its triangles
+/// facing outwards, to the temporary directory, and returns its path.
+pub(crate) fn cube_stl(name: &str) -> String {
+    let mut stl = String::from("solid cube\n");
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for side in [-0.5, 0.5] {
+            // counterclockwise around the outward normal
+            let mut corners = [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)];
+            if side < 0.0 {
+                corners.reverse();
+            }
+            let corners = corners.map(|(a, b)| {
+                let mut corner = [0.0; 3];
+                corner[axis] = side;
+                corner[u] = a;
+                corner[v] = b;
+                corner
+            });
+            let mut normal = [0.0; 3];
+            normal[axis] = 2.0 * side;
+
+            for triangle in [[0, 1, 2], [0, 2, 3]] {
+                stl += &format!(
+                    "facet normal {} {} {}\nouter loop\n",
+                    normal[0], normal[1], normal[2]
+                );
+                for [x, y, z] in triangle.map(|i| corners[i]) {
+                    stl += &format!("vertex {} {} {}\n", x, y, z);
+                }
+                stl += "endloop\nendfacet\n";
+            }
+        }
+    }
+    stl += "endsolid cube\n";
+
+    let path = std::env::temp_dir().join(format!("robotsim-{}-{}.stl", name, std::process::id()));
+    std::fs::write(&path, stl).unwrap();
+    path.to_str().unwrap().to_owned()
+}
@@ -0,0 +1,389 @@
+//! Occupancy voxel grids (e.g. from a mapping pipeline) as obstacles.
+//!
+//! The grid is split into chunks of [`VOXEL_CHUNK_SIZE`]³ voxels, each with its own
+//! collider, so that changing a few voxels only rebuilds the chunks they are in. Within a
+//! chunk, fully occupied octree cells are merged into a single box, so large occupied
+//! regions cost few shapes.
+
+use std::collections::HashMap;
+
+use eyre::{ensure, OptionExt, Result};
+use rapier3d::math::{Isometry, Real};
+use rapier3d::prelude::{ColliderBuilder, ColliderHandle, SharedShape};
+
+use super::{Obstacle, Robot, RobotError};
+use crate::collision_checker::ColliderBuilderActivateRobotLinkCollision;
+
+/// Number of voxels along each side of a chunk. This is a power of two, so that a chunk
+/// is the root of an octree.
+pub const VOXEL_CHUNK_SIZE: usize = 16;
+
+/// A dense occupancy grid. Voxel `[i, j, k]` spans `[i, i + 1] * voxel_size` along x (and
+/// likewise along y and z) in the frame of the grid.
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    dims: [usize; 3],
+    voxel_size: Real,
+    /// occupancy in C order, i.e. the last index varies fastest (as numpy arrays)
+    occupancy: Vec<bool>,
+    /// collider of every chunk with at least one occupied voxel
+    chunks: HashMap<[usize; 3], ColliderHandle>,
+}
+
+impl VoxelGrid {
+    pub fn dims(&self) -> [usize; 3] {
+        self.dims
+    }
+
+    pub fn voxel_size(&self) -> Real {
+        self.voxel_size
+    }
+
+    /// Whether the voxel is occupied. Voxels outside the grid are free.
+    pub fn is_occupied(&self, voxel: [usize; 3]) -> bool {
+        self.index(voxel).is_some_and(|idx| self.occupancy[idx])
+    }
+
+    pub fn num_occupied(&self) -> usize {
+        self.occupancy.iter().filter(|occupied| **occupied).count()
+    }
+
+    fn index(&self, [i, j, k]: [usize; 3]) -> Option<usize> {
+        let [nx, ny, nz] = self.dims;
+        (i < nx && j < ny && k < nz).then(|| (i * ny + j) * nz + k)
+    }
+
+    fn num_chunks(&self) -> [usize; 3] {
+        self.dims.map(|n| n.div_ceil(VOXEL_CHUNK_SIZE))
+    }
+
+    /// Boxes (first voxel, number of voxels along each side) covering exactly the occupied
+    /// voxels of a chunk, merging fully occupied octree cells.
+    fn chunk_boxes(&self, chunk: [usize; 3]) -> Vec<([usize; 3], usize)> {
+        let corner = chunk.map(|c| c * VOXEL_CHUNK_SIZE);
+        let mut boxes = Vec::new();
+        if self.octree_boxes(corner, VOXEL_CHUNK_SIZE, &mut boxes) {
+            boxes.push((corner, VOXEL_CHUNK_SIZE));
+        }
+        boxes
+    }
+
+    /// Adds the boxes of the octree cell of the given corner and size, and returns whether
+    /// the cell is fully occupied (in which case the caller merges it instead).
+    fn octree_boxes(
+        &self,
+        corner: [usize; 3],
+        size: usize,
+        boxes: &mut Vec<([usize; 3], usize)>,
+    ) -> bool {
+        if size == 1 {
+            return self.is_occupied(corner);
+        }
+
+        let half = size / 2;
+        let mut full = [false; 8];
+        let num_boxes = boxes.len();
+        for (octant, full) in full.iter_mut().enumerate() {
+            let offset = [octant & 1, (octant >> 1) & 1, (octant >> 2) & 1];
+            let child = [0, 1, 2].map(|axis| corner[axis] + offset[axis] * half);
+            *full = self.octree_boxes(child, half, boxes);
+        }
+
+        if full.iter().all(|full| *full) {
+            // the children did not add anything: let the parent merge this cell
+            debug_assert_eq!(boxes.len(), num_boxes);
+            return true;
+        }
+        for (octant, _) in full.iter().enumerate().filter(|(_, full)| **full) {
+            let offset = [octant & 1, (octant >> 1) & 1, (octant >> 2) & 1];
+            boxes.push((
+                [0, 1, 2].map(|axis| corner[axis] + offset[axis] * half),
+                half,
+            ));
+        }
+        false
+    }
+
+    /// Collision shape of a chunk, relative to the frame of the grid, or `None` if the
+    /// chunk is empty.
+    fn chunk_shape(&self, chunk: [usize; 3]) -> Option<SharedShape> {
+        let shapes: Vec<_> = self
+            .chunk_boxes(chunk)
+            .into_iter()
+            .map(|(corner, size)| {
+                let half_extent = size as Real * self.voxel_size / 2.0;
+                let center = corner.map(|c| c as Real * self.voxel_size + half_extent);
+                (
+                    Isometry::translation(center[0], center[1], center[2]),
+                    SharedShape::cuboid(half_extent, half_extent, half_extent),
+                )
+            })
+            .collect();
+        (!shapes.is_empty()).then(|| SharedShape::compound(shapes))
+    }
+}
+
+impl Robot {
+    /// Adds an occupancy grid as an obstacle, replacing any existing obstacle with the
+    /// same name. `occupancy` holds `dims[0] * dims[1] * dims[2]` values in C order, and
+    /// `pose` is the pose of the corner of voxel `[0, 0, 0]`.
+    pub fn add_voxel_grid_obstacle(
+        &mut self,
+        name: &str,
+        dims: [usize; 3],
+        voxel_size: Real,
+        occupancy: &[bool],
+        pose: Isometry<Real>,
+    ) -> Result<()> {
+        ensure!(
+            voxel_size > 0.0,
+            "Voxel size must be positive (got {})",
+            voxel_size
+        );
+        ensure!(
+            occupancy.len() == dims.iter().product::<usize>(),
+            "Expected {} occupancy values for a grid of {:?}, got {}",
+            dims.iter().product::<usize>(),
+            dims,
+            occupancy.len()
+        );
+
+        if self.obstacles.contains_key(name) {
+            self.remove_obstacle(name)?;
+        }
+
+        let grid = VoxelGrid {
+            dims,
+            voxel_size,
+            occupancy: occupancy.to_vec(),
+            chunks: HashMap::new(),
+        };
+        let [cx, cy, cz] = grid.num_chunks();
+        let chunks: Vec<_> = (0..cx)
+            .flat_map(|i| (0..cy).flat_map(move |j| (0..cz).map(move |k| [i, j, k])))
+            .collect();
+
+        self.obstacles.insert(
+            name.to_owned(),
+            Obstacle {
+                pose,
+                colliders: Vec::new(),
+                voxel_grid: Some(grid),
+            },
+        );
+        self.rebuild_voxel_chunks(name, chunks)
+    }
+
+    /// Sets the occupancy of individual voxels of a voxel grid obstacle. Only the chunks
+    /// containing the changed voxels are rebuilt.
+    pub fn set_voxels(&mut self, name: &str, voxels: &[([usize; 3], bool)]) -> Result<()> {
+        let grid = self.voxel_grid_mut(name)?;
+
+        // check every voxel first, so that the grid is left untouched on error
+        let indices = voxels
+            .iter()
+            .map(|(voxel, _)| {
+                grid.index(*voxel).ok_or_else(|| {
+                    eyre::eyre!("Voxel {:?} is outside of the grid {:?}", voxel, grid.dims)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut chunks = Vec::new();
+        for ((voxel, occupied), idx) in voxels.iter().zip(indices) {
+            if grid.occupancy[idx] != *occupied {
+                grid.occupancy[idx] = *occupied;
+                chunks.push(voxel.map(|v| v / VOXEL_CHUNK_SIZE));
+            }
+        }
+        chunks.sort_unstable();
+        chunks.dedup();
+        self.rebuild_voxel_chunks(name, chunks)
+    }
+
+    /// The voxel grid of an obstacle, if it is one.
+    pub fn voxel_grid(&self, name: &str) -> Result<Option<&VoxelGrid>> {
+        Ok(self
+            .obstacles
+            .get(name)
+            .ok_or_else(|| RobotError::UnknownObstacle(name.to_owned()))?
+            .voxel_grid
+            .as_ref())
+    }
+
+    fn voxel_grid_mut(&mut self, name: &str) -> Result<&mut VoxelGrid> {
+        self.obstacles
+            .get_mut(name)
+            .ok_or_else(|| RobotError::UnknownObstacle(name.to_owned()))?
+            .voxel_grid
+            .as_mut()
+            .ok_or_else(|| eyre::eyre!("Obstacle '{}' is not a voxel grid", name))
+    }
+
+    /// Replaces the colliders of the given chunks, adding or removing them as chunks
+    /// become occupied or empty.
+    fn rebuild_voxel_chunks(&mut self, name: &str, chunks: Vec<[usize; 3]>) -> Result<()> {
+        let obstacle = self
+            .obstacles
+            .get_mut(name)
+            .ok_or_else(|| RobotError::UnknownObstacle(name.to_owned()))?;
+        let grid = obstacle
+            .voxel_grid
+            .as_mut()
+            .ok_or_eyre("obstacle is not a voxel grid")?;
+        let checker = &mut self.collision_checker;
+
+        for chunk in chunks {
+            let shape = grid.chunk_shape(chunk);
+            match (grid.chunks.get(&chunk).copied(), shape) {
+                (Some(handle), Some(shape)) => {
+                    checker
+                        .collider_set
+                        .get_mut(handle)
+                        .ok_or_eyre("cannot find collider")?
+                        .set_shape(shape);
+                }
+                (Some(handle), None) => {
+                    checker.collider_set.remove(
+                        handle,
+                        &mut checker.island_manager,
+                        &mut checker.rigid_body_set,
+                        false,
+                    );
+                    grid.chunks.remove(&chunk);
+                    obstacle.colliders.retain(|(h, _)| *h != handle);
+                }
+                (None, Some(shape)) => {
+                    // the shapes of a chunk are relative to the grid frame
+                    let local_pose = Isometry::identity();
+                    let collider = ColliderBuilder::new(shape)
+                        .position(obstacle.pose * local_pose)
+                        .activate_as_environment_obstacle()
+                        .build();
+                    let handle = checker.collider_set.insert(collider);
+                    grid.chunks.insert(chunk, handle);
+                    obstacle.colliders.push((handle, local_pose));
+                }
+                (None, None) => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(dims: [usize; 3], occupied: impl Fn([usize; 3]) -> bool) -> VoxelGrid {
+        let mut occupancy = Vec::new();
+        for i in 0..dims[0] {
+            for j in 0..dims[1] {
+                for k in 0..dims[2] {
+                    occupancy.push(occupied([i, j, k]));
+                }
+            }
+        }
+        VoxelGrid {
+            dims,
+            voxel_size: 0.1,
+            occupancy,
+            chunks: HashMap::new(),
+        }
+    }
+
+    fn volume(boxes: &[([usize; 3], usize)]) -> usize {
+        boxes.iter().map(|(_, size)| size.pow(3)).sum()
+    }
+
+    #[test]
+    fn merges_full_chunk_into_one_box() {
+        let n = VOXEL_CHUNK_SIZE;
+        let grid = grid([n, n, n], |_| true);
+        assert_eq!(grid.chunk_boxes([0, 0, 0]), vec![([0, 0, 0], n)]);
+    }
+
+    #[test]
+    fn merges_full_octree_cells() {
+        let n = VOXEL_CHUNK_SIZE;
+        let grid = grid([n, n, n], |voxel| {
+            voxel.iter().all(|v| *v < 2) || voxel == [5, 5, 5]
+        });
+        let mut boxes = grid.chunk_boxes([0, 0, 0]);
+        boxes.sort_unstable();
+        assert_eq!(boxes, vec![([0, 0, 0], 2), ([5, 5, 5], 1)]);
+
+        let empty = self::grid([n, n, n], |_| false);
+        assert!(empty.chunk_boxes([0, 0, 0]).is_empty());
+        assert!(empty.chunk_shape([0, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn boxes_of_partial_chunk_stay_within_the_grid() {
+        let n = VOXEL_CHUNK_SIZE;
+        let grid = grid([n + 4, n, n], |_| true);
+        assert_eq!(grid.num_chunks(), [2, 1, 1]);
+
+        let boxes = grid.chunk_boxes([1, 0, 0]);
+        assert_eq!(volume(&boxes), 4 * n * n);
+        assert_eq!(boxes.len(), 16);
+        assert!(boxes
+            .iter()
+            .all(|(corner, size)| corner[0] >= n && corner[0] + size <= n + 4));
+    }
+
+    fn robot() -> Robot {
+        let urdf = urdf_rs::read_from_string(r#"<robot name="r"><link name="base" /></robot>"#);
+        Robot::from_urdf_robot(urdf.unwrap(), None).unwrap()
+    }
+
+    fn chunk_handles(robot: &Robot) -> HashMap<[usize; 3], ColliderHandle> {
+        robot.voxel_grid("map").unwrap().unwrap().chunks.clone()
+    }
+
+    #[test]
+    fn set_voxels_rebuilds_only_changed_chunks() {
+        let n = VOXEL_CHUNK_SIZE;
+        let mut robot = robot();
+        let dims = [n + 4, 4, 4];
+        let occupancy = vec![false; dims.iter().product()];
+        robot
+            .add_voxel_grid_obstacle("map", dims, 0.1, &occupancy, Isometry::identity())
+            .unwrap();
+        assert!(chunk_handles(&robot).is_empty());
+        assert!(robot.obstacles["map"].colliders.is_empty());
+
+        robot
+            .set_voxels("map", &[([1, 1, 1], true), ([n + 1, 0, 0], true)])
+            .unwrap();
+        let handles = chunk_handles(&robot);
+        assert_eq!(handles.len(), 2);
+        assert_eq!(robot.obstacles["map"].colliders.len(), 2);
+
+        // the first chunk is untouched when only the second one changes
+        robot.set_voxels("map", &[([n + 2, 0, 0], true)]).unwrap();
+        assert_eq!(chunk_handles(&robot)[&[0, 0, 0]], handles[&[0, 0, 0]]);
+
+        // emptied chunks lose their collider
+        robot
+            .set_voxels("map", &[([n + 1, 0, 0], false), ([n + 2, 0, 0], false)])
+            .unwrap();
+        let remaining = chunk_handles(&robot);
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[&[0, 0, 0]], handles[&[0, 0, 0]]);
+        assert_eq!(robot.obstacles["map"].colliders.len(), 1);
+        assert!(robot
+            .collision_checker
+            .collider_set
+            .get(handles[&[1, 0, 0]])
+            .is_none());
+
+        // an invalid voxel leaves the grid untouched
+        assert!(robot
+            .set_voxels("map", &[([0, 0, 0], true), ([n + 4, 0, 0], true)])
+            .is_err());
+        let grid = robot.voxel_grid("map").unwrap().unwrap();
+        assert!(!grid.is_occupied([0, 0, 0]));
+        assert_eq!(grid.num_occupied(), 1);
+    }
+}
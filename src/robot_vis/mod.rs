@@ -3,9 +3,11 @@ use bevy::app::App;
 use std::collections::HashMap;
 
 use bevy::prelude::*;
+use rapier3d::math::Isometry;
 use urdf_rs::Robot;
 
 use super::assets_loader::{self};
+use crate::robot::UrdfRobotOption;
 
 // use super::assets_loader::{self, rgba_from_visual};
 
@@ -40,8 +42,15 @@ pub struct RobotState {
     pub is_collision: bool,
     pub disable_texture: bool,
     pub robot_chain: k::Chain<f32>,
+    /// pose of the robot's root link in the world (z up)
+    pub base_pose: Isometry<f32>,
     pub link_names_to_entity: HashMap<String, Entity>,
     pub joint_link_map: HashMap<String, String>,
+    /// directory that relative mesh filenames of the urdf are resolved against
+    pub base_dir: Option<String>,
+    /// options of the robot's collision model; its virtual base joints (if any) are
+    /// already part of `urdf_robot`
+    pub robot_option: UrdfRobotOption,
 }
 
 impl RobotState {
@@ -55,12 +64,15 @@ impl RobotState {
         Self {
             joint_link_map: k::urdf::joint_to_link_map(&urdf_robot),
             robot_chain: urdf_robot.clone().into(),
+            base_pose: Isometry::identity(),
             urdf_robot,
             end_link_names,
             is_collision: false,
             disable_texture: false,
             // link_joint_map: k::urdf::link_to_joint_map(&urdf_robot),
             link_names_to_entity: Default::default(),
+            base_dir: None,
+            robot_option: UrdfRobotOption::default(),
         }
     }
 }
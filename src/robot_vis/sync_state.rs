@@ -2,6 +2,7 @@
 
 use bevy::prelude::*;
 
+use super::visuals::root_transform;
 use super::RobotRoot;
 
 use k;
@@ -14,10 +15,14 @@ pub fn plugin(app: &mut App) {
 }
 
 fn update_robot_visual(
-    mut robots: Query<&RobotState, (Changed<RobotState>, With<Children>, With<RobotRoot>)>,
-    mut transform_query: Query<&mut Transform, With<RobotLink>>,
+    mut robots: Query<
+        (&RobotState, &mut Transform),
+        (Changed<RobotState>, With<Children>, With<RobotRoot>),
+    >,
+    mut transform_query: Query<&mut Transform, (With<RobotLink>, Without<RobotRoot>)>,
 ) {
-    for robot_state in &mut robots {
+    for (robot_state, mut root) in &mut robots {
+        *root = root_transform(&robot_state.base_pose);
         let kinematic: &k::Chain<f32> = &robot_state.robot_chain;
 
         kinematic.update_transforms();
@@ -3,6 +3,7 @@ use bevy::{app::App, asset::LoadState};
 use std::f32::consts::*;
 
 use bevy::prelude::*;
+use rapier3d::math::Isometry;
 use urdf_rs::{Geometry, Pose};
 
 use crate::assets_loader::urdf::UrdfAsset;
@@ -14,16 +15,35 @@ use super::{
 
 // use super::assets_loader::{self, rgba_from_visual};
 
+use crate::robot::base::add_virtual_base_joints;
+use crate::robot::UrdfRobotOption;
 use crate::robot_vis::{RobotLink, RobotState};
 
 #[derive(Event, Debug, Default)]
-pub struct UrdfLoadRequest(pub String);
+pub struct UrdfLoadRequest {
+    /// Asset path of the urdf (relative to the assets folder, or absolute).
+    pub path: String,
+    /// Options of the robot's collision model, e.g. virtual base joints.
+    pub option: UrdfRobotOption,
+}
+
+impl UrdfLoadRequest {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            option: UrdfRobotOption::default(),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Eq, PartialEq, Resource, Default)]
-pub struct PendingUrdlAsset(pub Vec<Handle<assets_loader::urdf::UrdfAsset>>);
+pub struct PendingUrdlAsset(pub Vec<(Handle<assets_loader::urdf::UrdfAsset>, UrdfRobotOption)>);
 
 #[derive(Event, Debug)]
-pub struct UrdfAssetLoadedEvent(pub Handle<assets_loader::urdf::UrdfAsset>);
+pub struct UrdfAssetLoadedEvent(
+    pub Handle<assets_loader::urdf::UrdfAsset>,
+    pub UrdfRobotOption,
+);
 
 pub fn mesh_loader_plugin(app: &mut App) {
     app
@@ -66,7 +86,7 @@ fn load_urdf_request_handler(
     for event in reader.read() {
         pending_urdf_asset
             .0
-            .push(asset_server.load(event.0.clone()));
+            .push((asset_server.load(event.path.clone()), event.option.clone()));
     }
 }
 
@@ -81,15 +101,15 @@ fn track_urdf_loading_state(
 
         let mut tmp_vec = std::mem::take(&mut pending_urdf_asset.0);
 
-        for handle in &mut tmp_vec.drain(..) {
+        for (handle, option) in &mut tmp_vec.drain(..) {
             match server.get_load_states(handle.id()) {
                 Some((_, _, bevy::asset::RecursiveDependencyLoadState::Loaded)) => {
-                    writer.send(UrdfAssetLoadedEvent(handle));
+                    writer.send(UrdfAssetLoadedEvent(handle, option));
                 }
                 Some((_, _, bevy::asset::RecursiveDependencyLoadState::Failed)) => {
                     error!("Failed to load urdf asset");
                 }
-                _ => pending_urdf_asset.0.push(handle),
+                _ => pending_urdf_asset.0.push((handle, option)),
             };
         }
     }
@@ -99,6 +119,79 @@ fn track_urdf_loading_state(
     }
 }
 
+/// Converts an urdf pose (e.g. an `<origin>`) to a transform.
+pub(crate) fn pose_to_transform(pose: &Pose) -> Transform {
+    Transform {
+        translation: Vec3::new(pose.xyz[0] as f32, pose.xyz[1] as f32, pose.xyz[2] as f32),
+        // urdf's rpy are fixed-axis rotations about X, then Y, then Z
+        rotation: Quat::from_euler(
+            EulerRot::ZYX,
+            pose.rpy[2] as f32,
+            pose.rpy[1] as f32,
+            pose.rpy[0] as f32,
+        ),
+        scale: Vec3::ONE,
+    }
+}
+
+/// Transform of a robot's root entity: its base pose in the (z up) robot world, turned
+/// into bevy's y up world.
+pub(crate) fn root_transform(base_pose: &Isometry<f32>) -> Transform {
+    let (t, q) = (base_pose.translation.vector, base_pose.rotation);
+    Transform::from_rotation(Quat::from_rotation_x(-FRAC_PI_2))
+        * Transform {
+            translation: Vec3::new(t.x, t.y, t.z),
+            rotation: Quat::from_xyzw(q.i, q.j, q.k, q.w),
+            scale: Vec3::ONE,
+        }
+}
+
+/// The mesh of a primitive urdf geometry, with the rotation that aligns it with the urdf's
+/// axes, or `None` for meshes.
+fn primitive_mesh(geometry: &Geometry) -> Option<(Mesh, Quat)> {
+    // bevy's cylinder and capsule are along the Y axis, while urdf's are along Z
+    let to_z_axis = Quat::from_rotation_x(FRAC_PI_2);
+    match *geometry {
+        Geometry::Box { size } => Some((
+            Cuboid::new(size[0] as f32, size[1] as f32, size[2] as f32).into(),
+            Quat::IDENTITY,
+        )),
+        Geometry::Cylinder { radius, length } => Some((
+            Cylinder::new(radius as f32, length as f32).into(),
+            to_z_axis,
+        )),
+        Geometry::Capsule { radius, length } => Some((
+            Capsule3d::new(radius as f32, length as f32).into(),
+            to_z_axis,
+        )),
+        Geometry::Sphere { radius } => Some((Sphere::new(radius as f32).into(), Quat::IDENTITY)),
+        Geometry::Mesh { .. } => None,
+    }
+}
+
+/// A bundle (without material) that renders a primitive urdf geometry, or `None` for meshes.
+pub(crate) fn primitive_bundle(
+    geometry: &Geometry,
+    meshes: &mut Assets<Mesh>,
+) -> Option<PbrBundle> {
+    let (mesh, rotation) = primitive_mesh(geometry)?;
+    Some(PbrBundle {
+        mesh: meshes.add(mesh),
+        transform: Transform::from_rotation(rotation),
+        ..default()
+    })
+}
+
+/// The material for geometries without one, created on first use.
+fn default_material(
+    materials: &mut Assets<StandardMaterial>,
+    standard_default_material: &mut Option<Handle<StandardMaterial>>,
+) -> Handle<StandardMaterial> {
+    standard_default_material
+        .get_or_insert_with(|| materials.add(StandardMaterial { ..default() }))
+        .clone()
+}
+
 fn spawn_link(
     entity: &mut bevy::ecs::system::EntityCommands,
     materials: &mut ResMut<Assets<StandardMaterial>>,
@@ -109,64 +202,56 @@ fn spawn_link(
     geom_element: &Geometry,
     origin_element: &Pose,
 ) -> Entity {
-    match *geom_element {
-        urdf_rs::Geometry::Mesh { filename: _, scale } => {
-            let scale = scale.map_or_else(
-                || Vec3::ONE,
-                |val| Vec3::new(val[0] as f32, val[1] as f32, val[2] as f32),
-            );
-
-            // dbg!(origin_element);
-            // dbg!(&urdf_asset.meshes_and_materials);
-
-            entity
-                .insert(SpatialBundle::from_transform(
-                         Transform {
-                            translation: Vec3::new(
-                                origin_element.xyz[0] as f32,
-                                origin_element.xyz[1] as f32,
-                                origin_element.xyz[2] as f32,
-                            ),
-                            rotation: Quat::from_euler(
-                                EulerRot::XYZ,
-                                origin_element.rpy[0] as f32,
-                                origin_element.rpy[1] as f32,
-                                origin_element.rpy[2] as f32,
-                            ),
-                            scale: scale,
-                        },
-                ))
-                .with_children(|builder| {
-                    match meshes_and_materials.remove(mesh_material_key) {
-                    None => { error!("no mesh handles found for {:?}. But it should have been pre-loaded", mesh_material_key); }
-                    Some(mut meshes_and_materials) => {
-                        meshes_and_materials.drain(..).for_each(|(m, material)| {
-                            let mut bundle = PbrBundle {
-                                mesh: meshes.add(m),
-                                ..default()
-                            };
-                            bundle.material = match material {
-                                Some(material) => materials.add(material),
-                                None => {
-                                    if standard_default_material.is_none() {
-                                        // create standard material on demand
-                                        *standard_default_material =
-                                            Some(materials.add(StandardMaterial { ..default() }));
-                                    }
-                                    standard_default_material.as_ref().unwrap().clone()  // unwrap cannot fails as we've just added it
-                                }
-                            };
-
-                            builder.spawn(bundle);
-                        });
-                    }
-                }
-                });
-        }
-        _ => {
-            todo!();
-        }
+    let mut transform = pose_to_transform(origin_element);
+
+    if let Some((mesh, rotation)) = primitive_mesh(geom_element) {
+        let bundle = PbrBundle {
+            mesh: meshes.add(mesh),
+            material: default_material(materials, standard_default_material),
+            transform: Transform::from_rotation(rotation),
+            ..default()
+        };
+        entity
+            .insert(SpatialBundle::from_transform(transform))
+            .with_children(|builder| {
+                builder.spawn(bundle);
+            });
+        return entity.id();
+    }
+
+    if let Geometry::Mesh {
+        scale: Some(scale), ..
+    } = geom_element
+    {
+        transform.scale = Vec3::new(scale[0] as f32, scale[1] as f32, scale[2] as f32);
     }
+    entity
+        .insert(SpatialBundle::from_transform(transform))
+        .with_children(
+            |builder| match meshes_and_materials.remove(mesh_material_key) {
+                None => {
+                    error!(
+                        "no mesh handles found for {:?}. But it should have been pre-loaded",
+                        mesh_material_key
+                    );
+                }
+                Some(mut meshes_and_materials) => {
+                    meshes_and_materials.drain(..).for_each(|(m, material)| {
+                        let mut bundle = PbrBundle {
+                            mesh: meshes.add(m),
+                            ..default()
+                        };
+                        bundle.material = match material {
+                            Some(material) => materials.add(material),
+                            // create standard material on demand
+                            None => default_material(materials, standard_default_material),
+                        };
+
+                        builder.spawn(bundle);
+                    });
+                }
+            },
+        );
     entity.id()
 }
 
@@ -179,21 +264,28 @@ fn load_urdf_meshes(
     mut reader: EventReader<UrdfAssetLoadedEvent>,
 ) {
     for event in reader.read() {
-        let handle = &event.0;
+        let (handle, option) = (&event.0, &event.1);
 
         if let Some(urdf_asset) = urdf_assets.remove(handle) {
-            let urdf_robot = urdf_asset.robot;
+            let mut urdf_robot = urdf_asset.robot;
+            // the virtual links are appended, so the mesh indices of the links still hold
+            if let Err(e) = add_virtual_base_joints(&mut urdf_robot, option.base_joint) {
+                error!("Failed to add the virtual base joints: {}", e);
+                continue;
+            }
             let mut meshes_and_materials = urdf_asset.meshes_and_materials;
 
             let mut robot_state = RobotState::new(urdf_robot.clone(), [].into());
+            robot_state.base_dir = urdf_asset.base_dir;
+            robot_state.robot_option = option.clone();
 
             let mut standard_default_material = None;
 
             let mut robot_root = commands.spawn(RobotRoot);
             robot_root
                 .insert(Name::new(urdf_robot.name))
-                .insert(SpatialBundle::from_transform(Transform::from_rotation(
-                    Quat::from_rotation_x(-FRAC_PI_2),
+                .insert(SpatialBundle::from_transform(root_transform(
+                    &robot_state.base_pose,
                 )))
                 .with_children(|child_builder| {
                     for (i, l) in urdf_robot.links.iter().enumerate() {